@@ -1,7 +1,10 @@
 // This is free and unencumbered software released into the public domain.
 
 use asimov_telegram_module::{
-    FetchTarget, parse_resource_url,
+    FetchTarget, MessageOrder, MessageSearch,
+    media::{self, AvatarSize, MediaLimits, MediaStore},
+    output::{Output, OutputFormat},
+    parse_resource_url,
     telegram::{Client, Config},
 };
 use clientele::{
@@ -10,8 +13,7 @@ use clientele::{
     crates::clap::{self, Parser},
 };
 use futures::StreamExt as _;
-use miette::{IntoDiagnostic as _, Result, miette};
-// use oxrdf::{Literal, NamedNode, Triple};
+use miette::{Result, miette};
 
 use asimov_telegram_module::shared;
 
@@ -26,10 +28,43 @@ struct Options {
     #[arg(value_name = "COUNT", short = 'n', long)]
     limit: Option<usize>,
 
-    /// The output format.
+    /// The output format: jsonl (default), json, jsonld, turtle, or ntriples.
     #[arg(value_name = "FORMAT", short = 'o', long)]
     output: Option<String>,
 
+    /// The order of listed messages: desc (newest first, default) or asc (oldest first).
+    #[arg(value_name = "ORDER", long)]
+    order: Option<String>,
+
+    /// List all members of large supergroups by also searching them by name.
+    /// Members with names in some scripts may still be missed.
+    #[arg(long)]
+    exhaustive: bool,
+
+    /// Download message media into this directory, with files named by content hash.
+    #[arg(value_name = "DIR", long)]
+    download_media: Option<std::path::PathBuf>,
+
+    /// The largest media file to download, in bytes.
+    #[arg(value_name = "BYTES", long)]
+    max_file_size: Option<u64>,
+
+    /// Only download media of this MIME type, e.g. `image/*` (repeatable).
+    #[arg(value_name = "TYPE", long = "mime-type")]
+    mime_types: Vec<String>,
+
+    /// Download chat and user avatars of this size (small or big) into the data directory.
+    #[arg(value_name = "SIZE", long)]
+    avatars: Option<String>,
+
+    /// A jq expression to apply to JSON records instead of the built-in filter.
+    #[arg(value_name = "EXPR", long, conflicts_with = "filter_file")]
+    filter: Option<String>,
+
+    /// A file with a jq filter to apply to JSON records instead of the built-in filter.
+    #[arg(value_name = "FILE", long)]
+    filter_file: Option<std::path::PathBuf>,
+
     resource: String,
 }
 
@@ -55,8 +90,17 @@ async fn main() -> Result<SysexitsError> {
     }
 
     let target_resource = parse_resource_url(&options.resource)?;
+    let output_format: OutputFormat = options.output.as_deref().unwrap_or("jsonl").parse()?;
+    let message_order: MessageOrder = options.order.as_deref().unwrap_or("desc").parse()?;
 
     let data_dir = shared::get_data_dir()?;
+    let avatars = match options.avatars {
+        Some(ref size) => {
+            let store = MediaStore::new(data_dir.join(media::AVATARS_DIR_NAME))?;
+            Some((size.parse::<AvatarSize>()?, store))
+        }
+        None => None,
+    };
     let api_id = obfstr::obfstring!(env!("ASIMOV_TELEGRAM_API_ID"));
     let api_hash = obfstr::obfstring!(env!("ASIMOV_TELEGRAM_API_HASH"));
     let encryption_key = asimov_telegram_module::telegram::get_or_create_encryption_key()?;
@@ -74,60 +118,173 @@ async fn main() -> Result<SysexitsError> {
         return Err(miette!("Unauthorized. Run `asimov module config telegram`"));
     }
 
-    let filter = asimov_telegram_module::jq::filter();
+    let target_resource = client.resolve(target_resource).await?;
+
+    let media = options.download_media.map(MediaStore::new).transpose()?;
+    let limits = MediaLimits {
+        max_size: options.max_file_size,
+        mime_types: options.mime_types,
+    };
+
+    let filter = asimov_telegram_module::jq::load_filter(
+        options.filter.as_deref(),
+        options.filter_file.as_deref(),
+    )?;
+    let mut output = Output::new(output_format, &filter, std::io::stdout().lock())?;
 
     match target_resource {
-        FetchTarget::Chats => {
-            let chats = client
-                .get_chats()
-                .await?
+        FetchTarget::Contacts => {
+            let mut users = client.get_contacts(options.limit).await?.boxed();
+
+            while let Some(user) = users.next().await {
+                output.write(client.with_avatar(user?, avatars.as_ref()).await)?;
+            }
+        }
+        FetchTarget::Chats { list } => {
+            let chats = match list {
+                Some(list) => client.get_chat_list(list, options.limit).await?,
+                None => client
+                    .get_chats()
+                    .await?
+                    .into_values()
+                    .take(options.limit.unwrap_or(usize::MAX))
+                    .collect(),
+            };
+            for chat in chats {
+                output.write(client.with_avatar(chat, avatars.as_ref()).await)?;
+            }
+        }
+        FetchTarget::Folders => {
+            let folders = client.get_chat_folders().await?;
+            for folder in folders
                 .into_iter()
-                .take(options.limit.unwrap_or(usize::MAX));
-            for (_id, chat) in chats {
-                match filter.filter_json(chat) {
-                    Ok(filtered) => println!("{filtered}"),
-                    Err(jq::JsonFilterError::NoOutput) => (),
-                    Err(err) => tracing::error!(?err),
-                }
+                .take(options.limit.unwrap_or(usize::MAX))
+            {
+                output.write(folder)?;
             }
         }
-        FetchTarget::ChatMembers { chat_id } => {
+        FetchTarget::ChatMembers { chat_id, filter } => {
             let mut users = client
-                .get_chat_members(chat_id, options.limit)
-                .await?
-                .boxed();
+                .get_chat_members(chat_id, filter, options.exhaustive, options.limit)
+                .await?;
 
+            let mut count = 0usize;
             while let Some(user) = users.next().await {
-                let user = user?;
-                match filter.filter_json(user) {
-                    Ok(filtered) => println!("{filtered}"),
-                    Err(jq::JsonFilterError::NoOutput) => (),
-                    Err(err) => tracing::error!(?err, "Filter failed"),
+                output.write(user?)?;
+                count += 1;
+            }
+
+            if options.exhaustive
+                && let Some(member_count) = client.get_member_count(chat_id).await?
+            {
+                eprintln!("Found {count} of {member_count} members");
+                if count < member_count as usize {
+                    eprintln!("Members with names in scripts that aren't searched may be missing");
                 }
             }
         }
-        FetchTarget::ChatMessages { chat_id } => {
+        FetchTarget::ChatMessages { chat_id, range } => {
+            let mut msgs = client
+                .get_chat_history(chat_id, range, message_order, options.limit)
+                .await?;
+
+            while let Some(msg) = msgs.next().await {
+                output.write(client.message_record(msg?, media.as_ref(), &limits).await?)?;
+            }
+        }
+        FetchTarget::MessageReplies {
+            chat_id,
+            message_id,
+            range,
+        } => {
             let mut msgs = client
-                .get_chat_history(chat_id, None, options.limit)
+                .get_message_replies(chat_id, message_id, range, message_order, options.limit)
+                .await?;
+
+            while let Some(msg) = msgs.next().await {
+                output.write(client.message_record(msg?, media.as_ref(), &limits).await?)?;
+            }
+        }
+        FetchTarget::SearchMessages { search } => {
+            let mut msgs = client.search_messages(search, options.limit).await?;
+
+            while let Some(msg) = msgs.next().await {
+                output.write(client.message_record(msg?, media.as_ref(), &limits).await?)?;
+            }
+        }
+        FetchTarget::ChatSearch { chat_id, search } => {
+            let mut msgs = client
+                .search_chat_messages(chat_id, search, options.limit)
+                .await?;
+
+            while let Some(msg) = msgs.next().await {
+                output.write(client.message_record(msg?, media.as_ref(), &limits).await?)?;
+            }
+        }
+        FetchTarget::ChatMedia { chat_id, filter } => {
+            let search = MessageSearch {
+                filter: Some(filter),
+                ..Default::default()
+            };
+            let mut msgs = client
+                .search_chat_messages(chat_id, search, options.limit)
+                .await?;
+
+            while let Some(msg) = msgs.next().await {
+                output.write(client.message_record(msg?, media.as_ref(), &limits).await?)?;
+            }
+        }
+        FetchTarget::UserChats { user_id } => {
+            let mut chats = client
+                .get_groups_in_common(user_id, options.limit)
                 .await?
                 .boxed();
 
+            while let Some(chat) = chats.next().await {
+                output.write(client.with_avatar(chat?, avatars.as_ref()).await)?;
+            }
+        }
+        FetchTarget::UserPhotos { user_id } => {
+            let mut photos = client
+                .get_user_profile_photos(user_id, options.limit)
+                .boxed();
+
+            while let Some(photo) = photos.next().await {
+                output.write(client.with_avatar(photo?, avatars.as_ref()).await)?;
+            }
+        }
+        FetchTarget::ChatTopics { chat_id } => {
+            let mut topics = client
+                .get_forum_topics(chat_id, options.limit)
+                .await?
+                .boxed();
+
+            while let Some(topic) = topics.next().await {
+                output.write(topic?)?;
+            }
+        }
+        FetchTarget::ChatTopicMessages {
+            chat_id,
+            topic_id,
+            range,
+        } => {
+            let mut msgs = client
+                .get_message_thread_history(chat_id, topic_id, range, message_order, options.limit)
+                .await?;
+
             while let Some(msg) = msgs.next().await {
-                let msg = serde_json::to_value(msg?).into_diagnostic()?;
-                match filter.filter_json(msg) {
-                    Ok(filtered) => println!("{filtered}"),
-                    Err(jq::JsonFilterError::NoOutput) => (),
-                    Err(err) => tracing::error!(?err, "Filter failed"),
-                }
+                output.write(client.message_record(msg?, media.as_ref(), &limits).await?)?;
             }
         }
         target => {
-            // just FetchTarget::Chats
+            // Single resources, such as a chat, message, user or file, are for the fetcher.
             return Err(miette!(
                 "{target} is not a valid target resource for cataloger"
             ));
         }
     }
 
+    output.finish()?;
+
     Ok(EX_OK)
 }
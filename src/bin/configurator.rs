@@ -1,13 +1,20 @@
 // This is free and unencumbered software released into the public domain.
 
-use asimov_telegram_module::telegram::{Client, Config};
+use asimov_telegram_module::{
+    date, jq, media,
+    telegram::{self, AuthStep, Client, Config},
+};
 use clientele::{
     StandardOptions,
     SysexitsError::{self, *},
-    crates::clap::{self, Parser},
+    crates::clap::{self, Parser, Subcommand},
+};
+use miette::{IntoDiagnostic as _, Result, WrapErr as _, miette};
+use serde_json::{Value, json};
+use std::{
+    io::{BufRead, Write},
+    path::Path,
 };
-use miette::{Result, miette};
-use std::io::{BufRead, Write};
 
 use asimov_telegram_module::shared;
 
@@ -17,21 +24,405 @@ use asimov_telegram_module::shared;
 struct Options {
     #[clap(flatten)]
     flags: StandardOptions,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Log in by scanning a QR code with Telegram on a device that is already logged in.
+    #[arg(long)]
+    qr: bool,
+
+    /// The phone number to log in with, instead of asking for it.
+    /// Defaults to $ASIMOV_TELEGRAM_PHONE.
+    #[arg(value_name = "PHONE", long)]
+    phone: Option<String>,
+
+    /// A shell command that prints the login code, instead of asking for it.
+    #[arg(value_name = "COMMAND", long)]
+    code_command: Option<String>,
+
+    /// The environment variable holding the 2FA password, instead of asking for it.
+    #[arg(value_name = "VAR", long)]
+    password_env: Option<String>,
+
+    /// Exchange login steps as JSON lines on stdin and stdout, and print the
+    /// status as a JSON object, for use in scripts.
+    #[arg(long, global = true)]
+    json: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Show the logged-in account, the current session and the database size.
+    ///
+    /// TDLib doesn't expose which data center the account is on, so it isn't shown.
+    Status,
+
+    /// Log out, which also deletes the local database.
+    Logout,
+
+    /// Delete the local database and its encryption key, without logging out.
+    ///
+    /// Downloaded avatars and a `filter.jq` in the data directory are kept.
+    Reset {
+        /// Don't ask for confirmation.
+        #[arg(short = 'y', long)]
+        yes: bool,
+    },
 }
 
 fn ask(prompt: &str) -> String {
+    loop {
+        let answer = read_line(prompt);
+        if !answer.is_empty() {
+            break answer;
+        }
+    }
+}
+
+/// Asks a yes/no question. Anything but `y`, including no answer at the end
+/// of input, means no.
+fn confirm(prompt: &str) -> bool {
+    read_line(prompt).trim().eq_ignore_ascii_case("y")
+}
+
+fn read_line(prompt: &str) -> String {
     let mut stdout = std::io::stdout().lock();
-    let mut lines = std::io::stdin().lock().lines();
+    write!(&mut stdout, "{prompt}").unwrap();
+    stdout.flush().unwrap();
+    match std::io::stdin().lock().lines().next() {
+        Some(Ok(line)) => line,
+        _ => String::new(),
+    }
+}
 
-    loop {
-        write!(&mut stdout, "{prompt}").unwrap();
-        stdout.flush().unwrap();
-        if let Some(Ok(password)) = lines.next()
-            && !password.is_empty()
-        {
-            break password;
+/// Asks for the answers to login steps, either interactively or, with
+/// `--json`, as JSON lines: each step is announced on stdout with an event
+/// like `{"need":"code"}`, and answered on stdin with `{"code":"12345"}`.
+struct Prompter {
+    json: bool,
+}
+
+impl Prompter {
+    /// Asks for the answer to a step, with `details` added to its JSON event.
+    fn ask(&self, need: &str, prompt: &str, details: Value) -> Result<String> {
+        if !self.json {
+            return Ok(ask(prompt));
+        }
+        self.answer(need, details)
+    }
+
+    /// Like `ask`, but without echoing what is typed, and allowing empty answers.
+    fn ask_secret(&self, need: &str, prompt: &str, details: Value) -> Result<String> {
+        if !self.json {
+            return rpassword::prompt_password(prompt).into_diagnostic();
+        }
+        self.answer(need, details)
+    }
+
+    fn answer(&self, need: &str, details: Value) -> Result<String> {
+        let answer = self.exchange(need, details)?;
+        answer
+            .get(need)
+            .and_then(Value::as_str)
+            .map(String::from)
+            .ok_or_else(|| miette!("Expected an answer like {{\"{need}\":\"...\"}}"))
+    }
+
+    /// Announces a step as a JSON event and reads the answer to it.
+    fn exchange(&self, need: &str, details: Value) -> Result<serde_json::Map<String, Value>> {
+        self.event(need, details);
+        let line = std::io::stdin()
+            .lock()
+            .lines()
+            .next()
+            .ok_or_else(|| miette!("Unexpected end of input, waiting for `{need}`"))?
+            .into_diagnostic()?;
+        serde_json::from_str(&line).map_err(|e| miette!("Invalid answer {line:?}: {e}"))
+    }
+
+    fn event(&self, need: &str, details: Value) {
+        let mut event = json!({ "need": need });
+        if let (Some(event), Value::Object(details)) = (event.as_object_mut(), details) {
+            event.extend(details);
+        }
+        println!("{event}");
+    }
+
+    /// Reports an error the user can recover from, such as a mistyped code.
+    fn error(&self, message: impl core::fmt::Display) {
+        if self.json {
+            println!("{}", json!({ "error": message.to_string() }));
+        } else {
+            println!("{message}");
+        }
+    }
+
+    fn done(&self) {
+        if self.json {
+            println!("{}", json!({ "status": "ready" }));
+        }
+    }
+}
+
+/// Runs a `--code-command` and returns what it prints.
+fn run_code_command(command: &str) -> Result<String> {
+    #[cfg(windows)]
+    let output = std::process::Command::new("cmd")
+        .args(["/C", command])
+        .output();
+    #[cfg(not(windows))]
+    let output = std::process::Command::new("sh")
+        .args(["-c", command])
+        .output();
+
+    let output = output
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to run `{command}`"))?;
+    if !output.status.success() {
+        return Err(miette!("`{command}` failed with {}", output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().into())
+}
+
+/// How many 2FA passwords are tried before giving up.
+const MAX_PASSWORD_ATTEMPTS: usize = 3;
+
+/// Recovers the 2FA password with a code sent to the recovery email address
+/// of the account, setting a new password.
+async fn recover_password(client: &Client, prompter: &Prompter) -> Result<()> {
+    client.request_password_recovery().await?;
+    let email_pattern = match client.auth_step().await {
+        Some(AuthStep::Password {
+            recovery_email: Some(pattern),
+            ..
+        }) if !pattern.is_empty() => pattern,
+        _ => "your recovery email address".into(),
+    };
+
+    let code = prompter.ask(
+        "recovery_code",
+        &format!("Enter the code sent to {email_pattern}: "),
+        json!({ "email_pattern": email_pattern }),
+    )?;
+    let (new_password, new_hint) = if prompter.json {
+        let answer = prompter.exchange("new_password", json!({}))?;
+        let field = |key| {
+            answer
+                .get(key)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+        (field("new_password"), field("new_hint"))
+    } else {
+        let new_password = loop {
+            let new_password = rpassword::prompt_password(
+                "Enter a new password (or leave empty to turn off 2FA): ",
+            )
+            .into_diagnostic()?;
+            let repeated =
+                rpassword::prompt_password("Repeat the new password: ").into_diagnostic()?;
+            if new_password == repeated {
+                break new_password;
+            }
+            println!("The passwords don't match, try again please.");
+        };
+        (
+            new_password,
+            read_line("Enter a hint for the new password (optional): "),
+        )
+    };
+
+    if let Err(err) = client
+        .recover_password(&code, &new_password, &new_hint)
+        .await
+    {
+        prompter.error(err);
+    }
+    Ok(())
+}
+
+/// Prints that no account is logged in, along with the size of the
+/// database, either as text or as a JSON object.
+fn print_logged_out_status(data_dir: &Path, json: bool) {
+    let database_size = database_size(data_dir);
+    if json {
+        println!(
+            "{}",
+            json!({ "authorized": false, "database_size": database_size })
+        );
+    } else {
+        println!("Not logged in.");
+        println!("Database: {} ({database_size} bytes)", data_dir.display());
+    }
+}
+
+/// Prints the logged-in account, the current session and the size of the
+/// database, either as text or as a JSON object.
+async fn print_status(client: &Client, data_dir: &Path, json: bool) -> Result<()> {
+    if !client.is_authorised().await {
+        print_logged_out_status(data_dir, json);
+        return Ok(());
+    }
+    let database_size = database_size(data_dir);
+
+    let me = client.get_me().await?;
+    let session = client.get_current_session().await?;
+    let username = me["usernames"]["active_usernames"][0].as_str();
+    let logged_in = date::format_datetime(session.log_in_date.into());
+
+    if json {
+        let status = json!({
+            "authorized": true,
+            "user_id": me["id"],
+            "first_name": me["first_name"],
+            "last_name": me["last_name"],
+            "username": username,
+            "phone_number": me["phone_number"],
+            "session": {
+                "id": session.id,
+                "application_name": session.application_name,
+                "application_version": session.application_version,
+                "device_model": session.device_model,
+                "platform": session.platform,
+                "system_version": session.system_version,
+                "log_in_date": logged_in,
+            },
+            "database_size": database_size,
+        });
+        println!("{status}");
+        return Ok(());
+    }
+
+    let name = [&me["first_name"], &me["last_name"]]
+        .into_iter()
+        .filter_map(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    println!("Account: {name} (ID {})", me["id"]);
+    if let Some(username) = username {
+        println!("Username: @{username}");
+    }
+    if let Some(phone) = me["phone_number"].as_str().filter(|s| !s.is_empty()) {
+        println!("Phone: +{phone}");
+    }
+    println!(
+        "Session: {} {} on {} ({} {}), logged in {logged_in}",
+        session.application_name,
+        session.application_version,
+        session.device_model,
+        session.platform,
+        session.system_version,
+    );
+    println!("Database: {} ({database_size} bytes)", data_dir.display());
+    Ok(())
+}
+
+/// The TDLib database file, which is created on the first run.
+const DATABASE_FILE_NAME: &str = "td.binlog";
+
+/// Returns whether the data directory holds a TDLib database, i.e. whether
+/// the configurator has been run on this machine before.
+fn has_database(data_dir: &Path) -> bool {
+    data_dir.join(DATABASE_FILE_NAME).is_file()
+}
+
+/// Returns the total size of the database files in the data directory, in
+/// bytes.
+fn database_size(data_dir: &Path) -> u64 {
+    database_entries(data_dir)
+        .iter()
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => dir_size(&entry.path()),
+            _ => entry.metadata().map(|m| m.len()).unwrap_or_default(),
+        })
+        .sum()
+}
+
+/// Returns the total size of the files in a directory, in bytes.
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => dir_size(&entry.path()),
+            _ => entry.metadata().map(|m| m.len()).unwrap_or_default(),
+        })
+        .sum()
+}
+
+/// Returns the entries of the data directory that belong to the TDLib
+/// database, leaving out a user's `filter.jq` and downloaded avatars.
+fn database_entries(data_dir: &Path) -> Vec<std::fs::DirEntry> {
+    let Ok(entries) = std::fs::read_dir(data_dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            name != jq::FILTER_FILE_NAME && name != media::AVATARS_DIR_NAME
+        })
+        .collect()
+}
+
+/// Deletes the database in the data directory and its encryption key in
+/// the keyring.
+fn reset(data_dir: &Path) -> Result<()> {
+    for entry in database_entries(data_dir) {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => std::fs::remove_dir_all(&path),
+            _ => std::fs::remove_file(&path),
+        }
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to delete {}", path.display()))?;
+    }
+    telegram::delete_encryption_key()
+}
+
+/// Shows login QR codes until one has been scanned, showing a new one
+/// whenever the login token expires.
+async fn login_with_qr_code(client: &Client, prompter: &Prompter) -> Result<()> {
+    if client.qr_code_link().await.is_none() {
+        client.request_qr_code_authentication().await?;
+    }
+
+    let mut shown = None;
+    while let Some(link) = client.qr_code_link().await {
+        if shown.as_ref() != Some(&link) {
+            if prompter.json {
+                prompter.event("qr_confirmation", json!({ "link": link }));
+            } else {
+                print_qr_code(&link)?;
+            }
+            shown = Some(link);
         }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
     }
+    Ok(())
+}
+
+fn print_qr_code(link: &str) -> Result<()> {
+    use qrcode::{QrCode, render::unicode::Dense1x2};
+
+    let code = QrCode::new(link.as_bytes()).into_diagnostic()?;
+    // Inverted, so that the code reads as dark on light in dark terminals.
+    let image = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+
+    println!("{image}");
+    println!(
+        "Scan this QR code in Telegram on your phone, under Settings > Devices > Link Desktop Device."
+    );
+    Ok(())
 }
 
 #[tokio::main]
@@ -56,12 +447,42 @@ async fn main() -> Result<SysexitsError> {
     }
 
     let data_dir = shared::get_data_dir()?;
+
+    // Reset before the client opens the database, or creates a new key:
+    if let Some(Command::Reset { yes }) = options.command {
+        if !yes
+            && !confirm(&format!(
+                "Delete the Telegram database in {data_dir}? [y/n] "
+            ))
+        {
+            return Ok(EX_OK);
+        }
+        reset(data_dir.as_std_path())?;
+        println!("Deleted the Telegram database and its encryption key.");
+        return Ok(EX_OK);
+    }
+
+    // Don't create a key and a database just to find out there's no account:
+    if !has_database(data_dir.as_std_path()) {
+        match options.command {
+            Some(Command::Status) => {
+                print_logged_out_status(data_dir.as_std_path(), options.json);
+                return Ok(EX_OK);
+            }
+            Some(Command::Logout) => {
+                println!("Not logged in.");
+                return Ok(EX_OK);
+            }
+            Some(Command::Reset { .. }) | None => (),
+        }
+    }
+
     let api_id = obfstr::obfstring!(env!("ASIMOV_TELEGRAM_API_ID"));
     let api_hash = obfstr::obfstring!(env!("ASIMOV_TELEGRAM_API_HASH"));
-    let encryption_key = asimov_telegram_module::telegram::get_or_create_encryption_key()?;
+    let encryption_key = telegram::get_or_create_encryption_key()?;
 
     let config = Config {
-        database_directory: data_dir.into(),
+        database_directory: data_dir.clone().into(),
         api_id,
         api_hash,
         encryption_key,
@@ -69,40 +490,155 @@ async fn main() -> Result<SysexitsError> {
 
     let client = Client::new(config).unwrap().init().await.unwrap();
 
-    if client.is_authorised().await {
-        return Ok(EX_OK);
+    match options.command {
+        Some(Command::Status) => {
+            print_status(&client, data_dir.as_std_path(), options.json).await?;
+            return Ok(EX_OK);
+        }
+        Some(Command::Logout) => {
+            client.log_out().await?;
+            println!("Logged out.");
+            return Ok(EX_OK);
+        }
+        Some(Command::Reset { .. }) | None => (),
     }
 
-    if !client.is_need_code().await {
-        let phone = ask("Enter phone: ");
-        client.send_auth_request(&phone).await?;
-    }
+    let prompter = Prompter { json: options.json };
+    let phone = options
+        .phone
+        .clone()
+        .or_else(|| std::env::var("ASIMOV_TELEGRAM_PHONE").ok());
 
-    let code = ask("Enter code: ");
-    client.send_auth_code(&code).await?;
+    let mut password_attempts = 0;
+    loop {
+        let Some(step) = client.auth_step().await else {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            continue;
+        };
 
-    let mut password_hint = String::new();
-    while client.is_need_password(&mut password_hint).await {
-        let password = ask("Enter password: ");
-        match client.send_auth_password(&password).await {
-            Ok(_) => break,
-            Err(e) if e.message == "PASSWORD_HASH_INVALID" => {
-                println!("Invalid password, try again please.");
-                continue;
+        match step {
+            AuthStep::Ready => break,
+            AuthStep::PhoneNumber if options.qr => login_with_qr_code(&client, &prompter).await?,
+            AuthStep::PhoneNumber => {
+                let phone = match phone {
+                    Some(ref phone) => phone.clone(),
+                    None => prompter.ask("phone", "Enter phone: ", json!({}))?,
+                };
+                client.send_auth_request(&phone).await?;
             }
-            Err(e) => {
-                return Err(miette!(
-                    "Failed to confirm authentication password: {}",
-                    e.message
-                ));
+            // Answers given on the command line aren't retried, as they
+            // would most likely be wrong again.
+            AuthStep::Code => match options.code_command {
+                Some(ref command) => client.send_auth_code(&run_code_command(command)?).await?,
+                None => {
+                    let code = prompter.ask("code", "Enter code: ", json!({}))?;
+                    if let Err(err) = client.send_auth_code(&code).await {
+                        prompter.error(err);
+                    }
+                }
+            },
+            AuthStep::Password {
+                hint,
+                recovery_email,
+            } => {
+                let password = match options.password_env {
+                    Some(ref var) => std::env::var(var)
+                        .map_err(|_| miette!("The environment variable `{var}` is not set"))?,
+                    None => {
+                        if !hint.is_empty() && !prompter.json {
+                            println!("Password hint: {hint}");
+                        }
+                        let prompt = match recovery_email {
+                            Some(_) => "Enter password (or leave empty to recover it by email): ",
+                            None => "Enter password: ",
+                        };
+                        let password = prompter.ask_secret(
+                            "password",
+                            prompt,
+                            json!({ "hint": hint, "recoverable": recovery_email.is_some() }),
+                        )?;
+                        if password.is_empty() {
+                            if recovery_email.is_some() {
+                                recover_password(&client, &prompter).await?;
+                            }
+                            continue;
+                        }
+                        password
+                    }
+                };
+                password_attempts += 1;
+                match client.send_auth_password(&password).await {
+                    Ok(_) => (),
+                    Err(e)
+                        if e.message == "PASSWORD_HASH_INVALID"
+                            && options.password_env.is_none() =>
+                    {
+                        if password_attempts >= MAX_PASSWORD_ATTEMPTS {
+                            return Err(miette!(
+                                "Invalid password, giving up after {password_attempts} attempts"
+                            ));
+                        }
+                        prompter.error("Invalid password, try again please.");
+                    }
+                    Err(e) => {
+                        return Err(miette!(
+                            "Failed to confirm authentication password: {}",
+                            e.message
+                        ));
+                    }
+                }
+            }
+            AuthStep::OtherDeviceConfirmation { .. } => {
+                login_with_qr_code(&client, &prompter).await?
+            }
+            AuthStep::EmailAddress => {
+                let email = prompter.ask("email", "Enter email address: ", json!({}))?;
+                client.send_email_address(&email).await?;
+            }
+            AuthStep::EmailCode { email_pattern } => {
+                let code = prompter.ask(
+                    "email_code",
+                    &format!("Enter the code sent to {email_pattern}: "),
+                    json!({ "email_pattern": email_pattern }),
+                )?;
+                if let Err(err) = client.send_email_code(&code).await {
+                    prompter.error(err);
+                }
+            }
+            AuthStep::Registration { terms_of_service } => {
+                // Answering with a name accepts the terms of service.
+                let (first_name, last_name) = if prompter.json {
+                    let answer = prompter.exchange(
+                        "registration",
+                        json!({ "terms_of_service": terms_of_service }),
+                    )?;
+                    let name = |key| {
+                        answer
+                            .get(key)
+                            .and_then(Value::as_str)
+                            .unwrap_or_default()
+                            .to_string()
+                    };
+                    (name("first_name"), name("last_name"))
+                } else {
+                    println!("{terms_of_service}");
+                    if !confirm("Accept the terms of service? [y/n] ") {
+                        return Err(miette!("The terms of service were not accepted"));
+                    }
+                    (
+                        ask("Enter first name: "),
+                        read_line("Enter last name (optional): "),
+                    )
+                };
+                client.register_user(&first_name, &last_name).await?;
+            }
+            AuthStep::Closed => {
+                return Err(miette!("The Telegram client closed while logging in"));
             }
         }
     }
 
-    if !client.is_authorised().await {
-        // TODO: improve
-        return Err(miette!("Something went wrong, still unauthorized"));
-    }
+    prompter.done();
 
     Ok(EX_OK)
 }
@@ -2,6 +2,8 @@
 
 use asimov_telegram_module::{
     FetchTarget,
+    media::{self, AvatarSize, MediaLimits, MediaStore},
+    output::{Output, OutputFormat},
     telegram::{Client, Config},
 };
 use clientele::{
@@ -9,7 +11,7 @@ use clientele::{
     SysexitsError::{self, *},
     crates::clap::{self, Parser},
 };
-use miette::{Result, miette};
+use miette::{IntoDiagnostic as _, Result, miette};
 use std::sync::Arc;
 
 use asimov_telegram_module::{parse_resource_url, shared};
@@ -27,10 +29,42 @@ struct Options {
     #[arg(value_name = "COUNT", short = 'n', long)]
     limit: Option<usize>,
 
-    /// The output format.
+    /// The output format: jsonl (default), json, jsonld, turtle, or ntriples.
     #[arg(value_name = "FORMAT", short = 'o', long)]
     output: Option<String>,
 
+    /// Also fetch the message that a fetched message replies to.
+    #[arg(long)]
+    with_reply: bool,
+
+    /// Include the public link of a fetched message.
+    #[arg(long)]
+    with_link: bool,
+
+    /// Download message media into this directory, with files named by content hash.
+    #[arg(value_name = "DIR", long)]
+    download_media: Option<std::path::PathBuf>,
+
+    /// The largest media file to download, in bytes.
+    #[arg(value_name = "BYTES", long)]
+    max_file_size: Option<u64>,
+
+    /// Only download media of this MIME type, e.g. `image/*` (repeatable).
+    #[arg(value_name = "TYPE", long = "mime-type")]
+    mime_types: Vec<String>,
+
+    /// Download chat and user avatars of this size (small or big) into the data directory.
+    #[arg(value_name = "SIZE", long)]
+    avatars: Option<String>,
+
+    /// A jq expression to apply to JSON records instead of the built-in filter.
+    #[arg(value_name = "EXPR", long, conflicts_with = "filter_file")]
+    filter: Option<String>,
+
+    /// A file with a jq filter to apply to JSON records instead of the built-in filter.
+    #[arg(value_name = "FILE", long)]
+    filter_file: Option<std::path::PathBuf>,
+
     resource: String,
 }
 
@@ -52,8 +86,16 @@ async fn main() -> Result<SysexitsError> {
     }
 
     let target_resource = parse_resource_url(&options.resource)?;
+    let output_format: OutputFormat = options.output.as_deref().unwrap_or("jsonl").parse()?;
 
     let data_dir = shared::get_data_dir()?;
+    let avatars = match options.avatars {
+        Some(ref size) => {
+            let store = MediaStore::new(data_dir.join(media::AVATARS_DIR_NAME))?;
+            Some((size.parse::<AvatarSize>()?, store))
+        }
+        None => None,
+    };
     let api_id = obfstr::obfstring!(env!("ASIMOV_TELEGRAM_API_ID"));
     let api_hash = obfstr::obfstring!(env!("ASIMOV_TELEGRAM_API_HASH"));
     let encryption_key = asimov_telegram_module::telegram::get_or_create_encryption_key()?;
@@ -71,32 +113,81 @@ async fn main() -> Result<SysexitsError> {
         return Err(miette!("Unauthorized. Run `asimov module config telegram`"));
     }
 
-    let filter = asimov_telegram_module::jq::filter();
+    let target_resource = client.resolve(target_resource).await?;
+
+    let media = options.download_media.map(MediaStore::new).transpose()?;
+    let limits = MediaLimits {
+        max_size: options.max_file_size,
+        mime_types: options.mime_types,
+    };
+
+    let filter = asimov_telegram_module::jq::load_filter(
+        options.filter.as_deref(),
+        options.filter_file.as_deref(),
+    )?;
+    let mut output = Output::new(output_format, &filter, std::io::stdout().lock())?;
 
     match target_resource {
+        FetchTarget::Me => {
+            let user = client.get_me().await?;
+            output.write(client.with_avatar(user, avatars.as_ref()).await)?;
+        }
         FetchTarget::Chat { chat_id } => {
-            let info = client.get_chat_info(chat_id).await?;
-            match filter.filter_json(info) {
-                Ok(filtered) => println!("{filtered}"),
-                Err(jq::JsonFilterError::NoOutput) => (),
-                Err(err) => tracing::error!(?err, "Filter failed"),
+            let chat = client.get_chat_info(chat_id).await?;
+            output.write(client.with_avatar(chat, avatars.as_ref()).await)?;
+        }
+        FetchTarget::ChatMessage {
+            chat_id,
+            message_id,
+        } => {
+            let msg = client.get_message(chat_id, message_id).await?;
+            let is_reply = msg.reply_to.is_some();
+            let mut record = client.message_record(msg, media.as_ref(), &limits).await?;
+
+            if options.with_link {
+                match client.get_message_link(chat_id, message_id).await {
+                    Ok(link) => record["link"] = link.into(),
+                    Err(err) => tracing::warn!(%err, "No message link"),
+                }
+            }
+            output.write(record)?;
+
+            if options.with_reply && is_reply {
+                let reply = client.get_replied_message(chat_id, message_id).await?;
+                let reply = client
+                    .message_record(reply, media.as_ref(), &limits)
+                    .await?;
+                output.write(reply)?;
             }
         }
         FetchTarget::UserInfo { user_id } => {
             let user = client.get_user(user_id).await?;
-            match filter.filter_json(user) {
-                Ok(filtered) => println!("{filtered}"),
-                Err(jq::JsonFilterError::NoOutput) => (),
-                Err(err) => tracing::error!(?err, "Filter failed"),
-            }
+            output.write(client.with_avatar(user, avatars.as_ref()).await)?;
+        }
+        FetchTarget::File { file_id } => {
+            let file = client.get_file(file_id).await?;
+            let Some(file) = client.download_file(&file, None, &limits).await? else {
+                return Err(miette!("File {file_id} exceeds the --max-file-size limit"));
+            };
+            let local_path = match media {
+                Some(ref store) => store.store(std::path::Path::new(&file.local.path))?,
+                None => file.local.path.clone().into(),
+            };
+
+            let mut record =
+                serde_json::to_value(tdlib_rs::enums::File::File(file)).into_diagnostic()?;
+            record["local_path"] = local_path.display().to_string().into();
+            output.write(record)?;
         }
         target => {
-            // FetchTarget::Chats, FetchTarget::ChatMembers, FetchTarget::ChatMessages
+            // Listings, such as chats, members, messages or search results, are for the cataloger.
             return Err(miette!(
                 "{target} is not a valid target resource for fetcher"
             ));
         }
     }
 
+    output.finish()?;
+
     Ok(EX_OK)
 }
@@ -0,0 +1,135 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Conversions between Unix timestamps, as used by TDLib, and UTC dates.
+
+use alloc::{format, string::String};
+use miette::{Result, miette};
+
+/// Formats a Unix timestamp as an ISO 8601 date and time in UTC,
+/// e.g. `2025-01-01T00:00:00Z`.
+pub fn format_datetime(timestamp: i64) -> String {
+    let (days, secs) = (timestamp.div_euclid(86400), timestamp.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Parses a date (`2025-01-01`), a UTC date and time (`2025-01-01T12:30:00Z`)
+/// or a Unix timestamp (`1735689600`) into a Unix timestamp.
+pub fn parse_timestamp(input: &str) -> Result<i64> {
+    let invalid = || {
+        miette!(
+            "Invalid date: {input:?}, expected e.g. `2025-01-01`, `2025-01-01T12:30:00Z` or a Unix timestamp"
+        )
+    };
+
+    if !input.is_empty() && input.bytes().all(|b| b.is_ascii_digit()) {
+        return input.parse().map_err(|_| invalid());
+    }
+
+    let (date, time) = match input.split_once('T') {
+        Some((date, time)) => (date, Some(time.strip_suffix('Z').unwrap_or(time))),
+        None => (input, None),
+    };
+
+    let mut fields = date.splitn(3, '-').map(|s| s.parse::<i64>().ok());
+    let (Some(Some(year)), Some(Some(month)), Some(Some(day))) =
+        (fields.next(), fields.next(), fields.next())
+    else {
+        return Err(invalid());
+    };
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return Err(invalid());
+    }
+
+    let mut secs = 0;
+    if let Some(time) = time {
+        let mut fields = time.splitn(3, ':').map(|s| s.parse::<i64>().ok());
+        let (Some(Some(hours)), Some(Some(minutes))) = (fields.next(), fields.next()) else {
+            return Err(invalid());
+        };
+        let seconds = match fields.next() {
+            Some(Some(seconds)) => seconds,
+            Some(None) => return Err(invalid()),
+            None => 0,
+        };
+        if !(0..24).contains(&hours) || !(0..60).contains(&minutes) || !(0..60).contains(&seconds) {
+            return Err(invalid());
+        }
+        secs = hours * 3600 + minutes * 60 + seconds;
+    }
+
+    Ok(days_from_civil(year, month, day) * 86400 + secs)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// See: https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// See: https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString as _;
+
+    #[test]
+    fn test_format_datetime() {
+        assert_eq!(format_datetime(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_datetime(951782400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_datetime(1735689599), "2024-12-31T23:59:59Z");
+        assert_eq!(format_datetime(-1), "1969-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-01").unwrap(), 0);
+        assert_eq!(parse_timestamp("2000-02-29").unwrap(), 951782400);
+        assert_eq!(parse_timestamp("2025-01-01").unwrap(), 1735689600);
+        assert_eq!(parse_timestamp("2024-12-31T23:59:59Z").unwrap(), 1735689599);
+        assert_eq!(parse_timestamp("2024-12-31T23:59").unwrap(), 1735689540);
+        assert_eq!(parse_timestamp("1735689600").unwrap(), 1735689600);
+
+        for input in [
+            "",
+            "2025-13-01",
+            "2023-02-29",
+            "2025-01-01T24:00",
+            "yesterday",
+        ] {
+            let err = parse_timestamp(input).unwrap_err().to_string();
+            assert!(err.contains("Invalid date"), "{input}");
+        }
+    }
+}
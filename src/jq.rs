@@ -13,3 +13,158 @@ pub fn filter() -> &'static JsonFilter {
 pub fn filter() -> JsonFilter {
     include_str!("jq/filter.jq").parse().unwrap()
 }
+
+/// The name of the file in the module data directory that overrides the
+/// built-in filter.
+pub const FILTER_FILE_NAME: &str = "filter.jq";
+
+/// Loads the filter to apply to records.
+///
+/// A jq `expression` takes precedence over a filter `file`. Without either,
+/// a `filter.jq` in the module data directory overrides the built-in filter.
+#[cfg(feature = "std")]
+pub fn load_filter(
+    expression: Option<&str>,
+    file: Option<&std::path::Path>,
+) -> miette::Result<JsonFilter> {
+    use miette::{IntoDiagnostic, WrapErr, miette};
+    use std::{format, string::ToString};
+
+    let (source, origin) = if let Some(expression) = expression {
+        (expression.to_string(), "--filter".to_string())
+    } else if let Some(file) = file {
+        let source = std::fs::read_to_string(file)
+            .into_diagnostic()
+            .wrap_err_with(|| miette!("Failed to read filter file {}", file.display()))?;
+        (source, file.display().to_string())
+    } else {
+        return load_data_dir_filter(crate::shared::get_data_dir()?.as_std_path());
+    };
+
+    tracing::debug!(%origin, "using custom filter");
+
+    source
+        .parse::<JsonFilter>()
+        .map_err(|e| miette!("Invalid jq filter in {origin}: {e}"))
+}
+
+/// Loads the `filter.jq` in a data directory, or the built-in filter if
+/// there is none.
+#[cfg(feature = "std")]
+fn load_data_dir_filter(data_dir: &std::path::Path) -> miette::Result<JsonFilter> {
+    use miette::{IntoDiagnostic, WrapErr, miette};
+    use std::format;
+
+    let file = data_dir.join(FILTER_FILE_NAME);
+    let source = match std::fs::read_to_string(&file) {
+        Ok(source) => source,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(include_str!("jq/filter.jq").parse().unwrap());
+        }
+        Err(err) => {
+            return Err(err)
+                .into_diagnostic()
+                .wrap_err_with(|| miette!("Failed to read filter file {}", file.display()));
+        }
+    };
+
+    tracing::debug!(origin = %file.display(), "using custom filter");
+
+    source
+        .parse::<JsonFilter>()
+        .map_err(|e| miette!("Invalid jq filter in {}: {e}", file.display()))
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::{format, io::Write as _, string::ToString as _};
+
+    macro_rules! fixture {
+        ($name:literal) => {
+            (
+                include_str!(concat!("jq/fixtures/", $name, ".json")),
+                include_str!(concat!("jq/fixtures/", $name, ".expected.json")),
+            )
+        };
+    }
+
+    #[test]
+    fn test_filter_fixtures() {
+        let fixtures = [
+            fixture!("chat"),
+            fixture!("user"),
+            fixture!("chat_member"),
+            fixture!("message"),
+            fixture!("message_document"),
+            fixture!("chat_photo"),
+            fixture!("chat_folder"),
+            fixture!("forum_topic"),
+        ];
+
+        for (input, expected) in fixtures {
+            let input: Value = serde_json::from_str(input).unwrap();
+            let expected: Value = serde_json::from_str(expected).unwrap();
+            assert_eq!(filter().filter_json(input).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_filter_passthrough() {
+        let input = serde_json::json!({"@type": "supergroup", "@extra": "1", "id": 5});
+        let expected = serde_json::json!({"@type": "supergroup", "id": 5});
+        assert_eq!(filter().filter_json(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_load_filter() {
+        let filter = load_filter(Some("{id}"), None).unwrap();
+        let input = serde_json::json!({"@type": "chat", "id": 5, "title": "x"});
+        assert_eq!(
+            filter.filter_json(input).unwrap(),
+            serde_json::json!({"id": 5})
+        );
+
+        let path = std::env::temp_dir().join(format!(
+            "asimov-telegram-test-filter-{}.jq",
+            std::process::id()
+        ));
+        std::fs::File::create(&path)
+            .unwrap()
+            .write_all(b".title")
+            .unwrap();
+        let filter = load_filter(None, Some(&path)).unwrap();
+        let input = serde_json::json!({"@type": "chat", "id": 5, "title": "x"});
+        assert_eq!(filter.filter_json(input).unwrap(), serde_json::json!("x"));
+        std::fs::remove_file(&path).unwrap();
+
+        let err = load_filter(Some("{"), None).unwrap_err().to_string();
+        assert!(err.contains("Invalid jq filter in --filter"));
+    }
+
+    #[test]
+    fn test_load_data_dir_filter() {
+        let dir =
+            std::env::temp_dir().join(format!("asimov-telegram-filter-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = serde_json::json!({"@type": "chat", "@extra": "1", "id": 5});
+
+        // Without a `filter.jq`, the built-in filter applies:
+        let filter = load_data_dir_filter(&dir).unwrap();
+        assert_eq!(
+            filter.filter_json(input.clone()).unwrap(),
+            super::filter().filter_json(input.clone()).unwrap()
+        );
+
+        std::fs::write(dir.join(FILTER_FILE_NAME), ".id").unwrap();
+        let filter = load_data_dir_filter(&dir).unwrap();
+        assert_eq!(filter.filter_json(input).unwrap(), serde_json::json!(5));
+
+        std::fs::write(dir.join(FILTER_FILE_NAME), "{").unwrap();
+        let err = load_data_dir_filter(&dir).unwrap_err().to_string();
+        assert!(err.contains("Invalid jq filter in"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
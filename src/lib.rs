@@ -7,9 +7,13 @@ extern crate std;
 
 extern crate alloc;
 
-use alloc::{format, vec::Vec};
+use alloc::{format, string::String, vec::Vec};
 
+pub mod date;
 pub mod jq;
+pub mod media;
+pub mod output;
+pub mod rdf;
 pub mod shared;
 pub mod telegram;
 
@@ -17,31 +21,355 @@ use miette::{Result, miette};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum FetchTarget {
-    Chats,
-    Chat { chat_id: i64 },
-    ChatMembers { chat_id: i64 },
-    ChatMessages { chat_id: i64 },
-    UserInfo { user_id: i64 },
+    Me,
+    Contacts,
+    Chats {
+        list: Option<ChatList>,
+    },
+    Folders,
+    Chat {
+        chat_id: i64,
+    },
+    ChatMembers {
+        chat_id: i64,
+        filter: MemberFilter,
+    },
+    ChatMessages {
+        chat_id: i64,
+        range: MessageRange,
+    },
+    ChatMessage {
+        chat_id: i64,
+        message_id: i64,
+    },
+    MessageReplies {
+        chat_id: i64,
+        message_id: i64,
+        range: MessageRange,
+    },
+    ChatTopics {
+        chat_id: i64,
+    },
+    ChatSearch {
+        chat_id: i64,
+        search: MessageSearch,
+    },
+    ChatMedia {
+        chat_id: i64,
+        filter: MessageFilter,
+    },
+    ChatTopicMessages {
+        chat_id: i64,
+        topic_id: i64,
+        range: MessageRange,
+    },
+    PublicChat {
+        username: String,
+    },
+    PublicChatMessage {
+        username: String,
+        message_id: i64,
+    },
+    UserInfo {
+        user_id: i64,
+    },
+    UserPhotos {
+        user_id: i64,
+    },
+    UserChats {
+        user_id: i64,
+    },
+    File {
+        file_id: i32,
+    },
+    SearchMessages {
+        search: MessageSearch,
+    },
+}
+
+/// Restricts a message listing, e.g. `tg://chats/1234567/messages?since=2025-01-01`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MessageRange {
+    /// Only include messages sent at or after this Unix timestamp (`since`).
+    pub since: Option<i64>,
+    /// Only include messages sent before this Unix timestamp (`until`).
+    pub until: Option<i64>,
+    /// Start listing from this message ID (`from_id`).
+    pub from_id: Option<i64>,
+}
+
+impl MessageRange {
+    fn from_query(url: &url::Url) -> Result<Self> {
+        let mut range = Self::default();
+        for (key, value) in url.query_pairs() {
+            match &*key {
+                "since" => range.since = Some(date::parse_timestamp(&value)?),
+                "until" => range.until = Some(date::parse_timestamp(&value)?),
+                "from_id" => {
+                    range.from_id = Some(
+                        value
+                            .parse()
+                            .map_err(|e| miette!("Invalid message ID: {value:?}: {e}"))?,
+                    )
+                }
+                _ => {
+                    return Err(miette!(
+                        "Unknown query parameter `{key}` for message listing"
+                    ));
+                }
+            }
+        }
+        Ok(range)
+    }
+
+    pub fn contains(&self, date: i64) -> bool {
+        self.since.is_none_or(|since| date >= since) && self.until.is_none_or(|until| date < until)
+    }
+}
+
+/// Restricts a member listing, e.g. `tg://chats/1234567/members?filter=bots&q=news`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemberFilter {
+    /// Only include members with this role (`filter`).
+    pub role: MemberRole,
+    /// Only include members whose name or username contains this (`q`).
+    pub query: Option<String>,
+}
+
+impl MemberFilter {
+    fn from_query(url: &url::Url) -> Result<Self> {
+        let mut filter = Self::default();
+        for (key, value) in url.query_pairs() {
+            match &*key {
+                "filter" => filter.role = value.parse()?,
+                "q" if !value.is_empty() => filter.query = Some(value.into_owned()),
+                "q" => filter.query = None,
+                _ => {
+                    return Err(miette!(
+                        "Unknown query parameter `{key}` for member listing"
+                    ));
+                }
+            }
+        }
+        Ok(filter)
+    }
+}
+
+/// The member roles a member listing can be restricted to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemberRole {
+    /// All members, most recently active first (`recent`).
+    #[default]
+    Any,
+    Administrators,
+    Bots,
+    Banned,
+    Restricted,
+    Contacts,
+}
+
+impl core::str::FromStr for MemberRole {
+    type Err = miette::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "recent" => Ok(MemberRole::Any),
+            "administrators" => Ok(MemberRole::Administrators),
+            "bots" => Ok(MemberRole::Bots),
+            "banned" => Ok(MemberRole::Banned),
+            "restricted" => Ok(MemberRole::Restricted),
+            "contacts" => Ok(MemberRole::Contacts),
+            _ => Err(miette!(
+                "Unknown member filter `{s}`, expected one of: recent, administrators, bots, banned, restricted, contacts"
+            )),
+        }
+    }
+}
+
+/// A message search, e.g. `tg://chats/1234567/search?q=hello&filter=photo`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MessageSearch {
+    /// The text to search for (`q`).
+    pub query: String,
+    /// Only include messages sent by this user, or by this chat if negative
+    /// (`sender`).
+    pub sender_id: Option<i64>,
+    /// Only include messages with this kind of content (`filter`).
+    pub filter: Option<MessageFilter>,
+}
+
+impl MessageSearch {
+    fn from_query(url: &url::Url) -> Result<Self> {
+        let mut search = Self::default();
+        for (key, value) in url.query_pairs() {
+            match &*key {
+                "q" => search.query = value.into_owned(),
+                "sender" => {
+                    search.sender_id = Some(
+                        value
+                            .parse()
+                            .map_err(|e| miette!("Invalid sender ID: {value:?}: {e}"))?,
+                    )
+                }
+                "filter" => search.filter = Some(value.parse()?),
+                _ => {
+                    return Err(miette!(
+                        "Unknown query parameter `{key}` for message search"
+                    ));
+                }
+            }
+        }
+        Ok(search)
+    }
+}
+
+/// The kinds of message content a search can be restricted to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageFilter {
+    Photo,
+    Video,
+    Document,
+    Audio,
+    Voice,
+    Url,
+    Gif,
+}
+
+impl MessageFilter {
+    /// Parses the media kind of a `tg://chats/<id>/media/<kind>` URL.
+    pub fn from_media_kind(kind: &str) -> Result<Self> {
+        match kind {
+            "photos" => Ok(MessageFilter::Photo),
+            "videos" => Ok(MessageFilter::Video),
+            "documents" => Ok(MessageFilter::Document),
+            "audio" => Ok(MessageFilter::Audio),
+            "voice" => Ok(MessageFilter::Voice),
+            "links" => Ok(MessageFilter::Url),
+            "gifs" => Ok(MessageFilter::Gif),
+            _ => Err(miette!(
+                "Unknown media kind `{kind}`, expected one of: photos, videos, documents, audio, voice, links, gifs"
+            )),
+        }
+    }
+}
+
+impl core::str::FromStr for MessageFilter {
+    type Err = miette::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "photo" => Ok(MessageFilter::Photo),
+            "video" => Ok(MessageFilter::Video),
+            "document" => Ok(MessageFilter::Document),
+            "audio" => Ok(MessageFilter::Audio),
+            "voice" => Ok(MessageFilter::Voice),
+            "url" => Ok(MessageFilter::Url),
+            "gif" => Ok(MessageFilter::Gif),
+            _ => Err(miette!(
+                "Unknown message filter `{s}`, expected one of: photo, video, document, audio, voice, url, gif"
+            )),
+        }
+    }
+}
+
+/// A list of chats, e.g. `tg://chats?list=folder:2`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChatList {
+    Main,
+    Archive,
+    /// A chat folder, by ID.
+    Folder(i32),
+}
+
+impl core::str::FromStr for ChatList {
+    type Err = miette::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "main" => Ok(ChatList::Main),
+            "archive" => Ok(ChatList::Archive),
+            _ => match s.strip_prefix("folder:") {
+                Some(id) => Ok(ChatList::Folder(
+                    id.parse()
+                        .map_err(|e| miette!("Invalid folder ID: {id:?}: {e}"))?,
+                )),
+                None => Err(miette!(
+                    "Unknown chat list `{s}`, expected `main`, `archive` or `folder:<id>`"
+                )),
+            },
+        }
+    }
+}
+
+/// The order in which messages are listed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MessageOrder {
+    /// Newest first.
+    #[default]
+    Desc,
+    /// Oldest first.
+    Asc,
+}
+
+impl core::str::FromStr for MessageOrder {
+    type Err = miette::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "desc" => Ok(MessageOrder::Desc),
+            "asc" => Ok(MessageOrder::Asc),
+            _ => Err(miette!(
+                "Unknown message order `{s}`, expected `asc` or `desc`"
+            )),
+        }
+    }
 }
 
 impl alloc::fmt::Display for FetchTarget {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         use FetchTarget::*;
         match self {
-            Chats => write!(f, "chat list"),
+            Me => write!(f, "account info"),
+            Contacts => write!(f, "contact list"),
+            Chats { .. } => write!(f, "chat list"),
+            Folders => write!(f, "chat folder list"),
             Chat { .. } => write!(f, "chat info"),
             ChatMembers { .. } => write!(f, "chat member list"),
             ChatMessages { .. } => write!(f, "chat message list"),
+            ChatMessage { .. } | PublicChatMessage { .. } => write!(f, "chat message"),
+            MessageReplies { .. } => write!(f, "message reply list"),
+            ChatTopics { .. } => write!(f, "forum topic list"),
+            ChatTopicMessages { .. } => write!(f, "forum topic message list"),
+            ChatSearch { .. } | SearchMessages { .. } => write!(f, "message search"),
+            ChatMedia { .. } => write!(f, "chat media list"),
+            PublicChat { .. } => write!(f, "chat info"),
             UserInfo { .. } => write!(f, "user info"),
+            UserPhotos { .. } => write!(f, "user photo list"),
+            UserChats { .. } => write!(f, "common chat list"),
+            File { .. } => write!(f, "file"),
         }
     }
 }
 
+/// The hosts of public Telegram links, such as `https://t.me/durov`.
+pub const PUBLIC_LINK_HOSTS: &[&str] = &["t.me", "telegram.me", "telegram.dog"];
+
 pub fn parse_resource_url(url_str: &str) -> Result<FetchTarget> {
     let url: url::Url = url_str.parse().map_err(|e| miette!("Invalid URL: {e}"))?;
 
+    if matches!(url.scheme(), "http" | "https")
+        && url
+            .host_str()
+            .is_some_and(|host| PUBLIC_LINK_HOSTS.contains(&host))
+    {
+        return parse_public_link(&url, url_str);
+    }
+
     if url.scheme() != "tg" {
-        return Err(miette!("Unknown scheme `{}`, expected `tg`", url.scheme()));
+        return Err(miette!(
+            "Unknown scheme `{}`, expected `tg` or a https://t.me/ link",
+            url.scheme()
+        ));
     }
 
     // Handle both tg://host/path and tg:path formats
@@ -70,7 +398,28 @@ pub fn parse_resource_url(url_str: &str) -> Result<FetchTarget> {
     };
 
     match segments.as_slice() {
-        ["chats"] | ["chat"] => Ok(FetchTarget::Chats),
+        ["me"] => Ok(FetchTarget::Me),
+        ["contacts"] => Ok(FetchTarget::Contacts),
+        ["chats"] | ["chat"] => {
+            let mut list = None;
+            for (key, value) in url.query_pairs() {
+                match &*key {
+                    "list" => list = Some(value.parse()?),
+                    _ => {
+                        return Err(miette!("Unknown query parameter `{key}` for chat listing"));
+                    }
+                }
+            }
+            Ok(FetchTarget::Chats { list })
+        }
+        ["folders"] => Ok(FetchTarget::Folders),
+        ["folders", folder_id, "chats"] => Ok(FetchTarget::Chats {
+            list: Some(ChatList::Folder(
+                folder_id
+                    .parse()
+                    .map_err(|e| miette!("Invalid folder ID: {folder_id:?}: {e}"))?,
+            )),
+        }),
         ["chats", chat_id] | ["chat", chat_id] => Ok(FetchTarget::Chat {
             chat_id: chat_id
                 .parse()
@@ -81,6 +430,7 @@ pub fn parse_resource_url(url_str: &str) -> Result<FetchTarget> {
                 chat_id: chat_id
                     .parse()
                     .map_err(|e| miette!("Invalid chat ID: {chat_id:?}: {e}"))?,
+                filter: MemberFilter::from_query(&url)?,
             })
         }
         ["chats", chat_id, "messages"] | ["chat", chat_id, "messages"] => {
@@ -88,17 +438,211 @@ pub fn parse_resource_url(url_str: &str) -> Result<FetchTarget> {
                 chat_id: chat_id
                     .parse()
                     .map_err(|e| miette!("Invalid chat ID: {chat_id:?}: {e}"))?,
+                range: MessageRange::from_query(&url)?,
+            })
+        }
+        ["chats", chat_id, "messages", message_id] | ["chat", chat_id, "messages", message_id] => {
+            Ok(FetchTarget::ChatMessage {
+                chat_id: chat_id
+                    .parse()
+                    .map_err(|e| miette!("Invalid chat ID: {chat_id:?}: {e}"))?,
+                message_id: message_id
+                    .parse()
+                    .map_err(|e| miette!("Invalid message ID: {message_id:?}: {e}"))?,
+            })
+        }
+        ["chats", chat_id, "messages", message_id, "replies"]
+        | ["chat", chat_id, "messages", message_id, "replies"] => Ok(FetchTarget::MessageReplies {
+            chat_id: chat_id
+                .parse()
+                .map_err(|e| miette!("Invalid chat ID: {chat_id:?}: {e}"))?,
+            message_id: message_id
+                .parse()
+                .map_err(|e| miette!("Invalid message ID: {message_id:?}: {e}"))?,
+            range: MessageRange::from_query(&url)?,
+        }),
+        ["chats", chat_id, "search"] | ["chat", chat_id, "search"] => Ok(FetchTarget::ChatSearch {
+            chat_id: chat_id
+                .parse()
+                .map_err(|e| miette!("Invalid chat ID: {chat_id:?}: {e}"))?,
+            search: MessageSearch::from_query(&url)?,
+        }),
+        ["chats", chat_id, "media", kind] | ["chat", chat_id, "media", kind] => {
+            Ok(FetchTarget::ChatMedia {
+                chat_id: chat_id
+                    .parse()
+                    .map_err(|e| miette!("Invalid chat ID: {chat_id:?}: {e}"))?,
+                filter: MessageFilter::from_media_kind(kind)?,
             })
         }
+        ["search"] => {
+            let search = MessageSearch::from_query(&url)?;
+            if search.query.is_empty() {
+                return Err(miette!("Missing `q` parameter in `tg://search` link"));
+            }
+            if search.sender_id.is_some() {
+                return Err(miette!(
+                    "The `sender` parameter is only supported when searching a chat, e.g. `tg://chats/<id>/search`"
+                ));
+            }
+            Ok(FetchTarget::SearchMessages { search })
+        }
+        ["chats", chat_id, "topics"] | ["chat", chat_id, "topics"] => Ok(FetchTarget::ChatTopics {
+            chat_id: chat_id
+                .parse()
+                .map_err(|e| miette!("Invalid chat ID: {chat_id:?}: {e}"))?,
+        }),
+        ["chats", chat_id, "topics", topic_id, "messages"]
+        | ["chat", chat_id, "topics", topic_id, "messages"] => Ok(FetchTarget::ChatTopicMessages {
+            chat_id: chat_id
+                .parse()
+                .map_err(|e| miette!("Invalid chat ID: {chat_id:?}: {e}"))?,
+            topic_id: topic_id
+                .parse()
+                .map_err(|e| miette!("Invalid topic ID: {topic_id:?}: {e}"))?,
+            range: MessageRange::from_query(&url)?,
+        }),
         ["users", user_id] | ["user", user_id] => Ok(FetchTarget::UserInfo {
             user_id: user_id
                 .parse()
                 .map_err(|e| miette!("Invalid user ID: {user_id:?}: {e}"))?,
         }),
+        ["users", user_id, "photos"] | ["user", user_id, "photos"] => Ok(FetchTarget::UserPhotos {
+            user_id: user_id
+                .parse()
+                .map_err(|e| miette!("Invalid user ID: {user_id:?}: {e}"))?,
+        }),
+        ["users", user_id, "chats"] | ["user", user_id, "chats"] => Ok(FetchTarget::UserChats {
+            user_id: user_id
+                .parse()
+                .map_err(|e| miette!("Invalid user ID: {user_id:?}: {e}"))?,
+        }),
+        ["files", file_id] | ["file", file_id] => Ok(FetchTarget::File {
+            file_id: file_id
+                .parse()
+                .map_err(|e| miette!("Invalid file ID: {file_id:?}: {e}"))?,
+        }),
+        [kind @ ("resolve" | "privatepost" | "user")] => parse_deep_link(&url, kind),
         _ => Err(miette!("Unsupported URL format: {}", url_str)),
     }
 }
 
+/// Parses one of Telegram's own query-style deep links, e.g.
+/// `tg://resolve?domain=durov&post=42`, `tg://privatepost?channel=1234567&post=42`
+/// or `tg://user?id=1234567`.
+fn parse_deep_link(url: &url::Url, kind: &str) -> Result<FetchTarget> {
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+    let required = |name: &str| {
+        param(name).ok_or_else(|| miette!("Missing `{name}` parameter in `tg://{kind}` link"))
+    };
+    match kind {
+        "resolve" => {
+            let username = required("domain")?;
+            if !is_username(&username) {
+                return Err(miette!("Invalid username: {username:?}"));
+            }
+            match param("post") {
+                Some(post) => Ok(FetchTarget::PublicChatMessage {
+                    username,
+                    message_id: parse_post(&post)?,
+                }),
+                None => Ok(FetchTarget::PublicChat { username }),
+            }
+        }
+        "privatepost" => Ok(FetchTarget::ChatMessage {
+            chat_id: supergroup_chat_id(&required("channel")?)?,
+            message_id: parse_post(&required("post")?)?,
+        }),
+        _ => {
+            let user_id = required("id")?;
+            Ok(FetchTarget::UserInfo {
+                user_id: user_id
+                    .parse()
+                    .map_err(|e| miette!("Invalid user ID: {user_id:?}: {e}"))?,
+            })
+        }
+    }
+}
+
+/// Parses a public link, e.g. `https://t.me/durov`, `https://t.me/durov/42`
+/// or `https://t.me/c/1234567/42`.
+///
+/// Post numbers in links are server message IDs, which are converted into
+/// TDLib message IDs.
+fn parse_public_link(url: &url::Url, url_str: &str) -> Result<FetchTarget> {
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+
+    match segments.as_slice() {
+        ["c", internal_id] => Ok(FetchTarget::Chat {
+            chat_id: supergroup_chat_id(internal_id)?,
+        }),
+        ["c", internal_id, post] => Ok(FetchTarget::ChatMessage {
+            chat_id: supergroup_chat_id(internal_id)?,
+            message_id: parse_post(post)?,
+        }),
+        ["s", username] | [username] if is_username(username) => Ok(FetchTarget::PublicChat {
+            username: String::from(*username),
+        }),
+        ["s", username, post] | [username, post] if is_username(username) => {
+            Ok(FetchTarget::PublicChatMessage {
+                username: String::from(*username),
+                message_id: parse_post(post)?,
+            })
+        }
+        _ => Err(miette!("Unsupported URL format: {}", url_str)),
+    }
+}
+
+/// Converts a server message ID, as used in public links, into a TDLib message ID.
+pub fn message_id_from_server_id(server_id: i64) -> i64 {
+    server_id << 20
+}
+
+/// Parses the post number of a public link into a TDLib message ID.
+fn parse_post(post: &str) -> Result<i64> {
+    post.parse()
+        .ok()
+        .filter(|id| *id > 0)
+        .map(message_id_from_server_id)
+        .ok_or_else(|| miette!("Invalid message ID: {post:?}"))
+}
+
+/// Maps the channel ID of a `https://t.me/c/<id>` link onto the chat ID of
+/// the supergroup, i.e. `-100<id>`.
+fn supergroup_chat_id(internal_id: &str) -> Result<i64> {
+    internal_id
+        .parse::<i64>()
+        .ok()
+        .filter(|id| *id > 0)
+        .map(|id| -1_000_000_000_000 - id)
+        .ok_or_else(|| miette!("Invalid chat ID: {internal_id:?}"))
+}
+
+fn is_username(s: &str) -> bool {
+    // Paths like `joinchat`, `addstickers` or `share` are reserved by Telegram.
+    const RESERVED: &[&str] = &[
+        "c",
+        "s",
+        "joinchat",
+        "addstickers",
+        "addemoji",
+        "share",
+        "proxy",
+        "socks",
+    ];
+
+    !s.is_empty()
+        && !RESERVED.contains(&s)
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 #[cfg(test)]
 mod tests {
     use std::{string::ToString as _, vec};
@@ -110,22 +654,113 @@ mod tests {
         use FetchTarget::*;
 
         let test_cases = vec![
+            ("tg://me", Me),
+            ("tg:me", Me),
+            ("tg://contacts", Contacts),
+            ("tg:contacts", Contacts),
             ("tg://chat/12345", Chat { chat_id: 12345 }),
             ("tg://chats/12345", Chat { chat_id: 12345 }),
             ("tg:chat/12345", Chat { chat_id: 12345 }),
             ("tg:chats/12345", Chat { chat_id: 12345 }),
-            ("tg://chat/12345/members", ChatMembers { chat_id: 12345 }),
-            ("tg://chats/12345/members", ChatMembers { chat_id: 12345 }),
-            ("tg:chat/12345/members", ChatMembers { chat_id: 12345 }),
-            ("tg:chats/12345/members", ChatMembers { chat_id: 12345 }),
-            ("tg://chat/12345/messages", ChatMessages { chat_id: 12345 }),
-            ("tg://chats/12345/messages", ChatMessages { chat_id: 12345 }),
-            ("tg:chat/12345/messages", ChatMessages { chat_id: 12345 }),
-            ("tg:chats/12345/messages", ChatMessages { chat_id: 12345 }),
+            (
+                "tg://chat/12345/members",
+                ChatMembers {
+                    chat_id: 12345,
+                    filter: MemberFilter::default(),
+                },
+            ),
+            (
+                "tg://chats/12345/members",
+                ChatMembers {
+                    chat_id: 12345,
+                    filter: MemberFilter::default(),
+                },
+            ),
+            (
+                "tg:chat/12345/members",
+                ChatMembers {
+                    chat_id: 12345,
+                    filter: MemberFilter::default(),
+                },
+            ),
+            (
+                "tg:chats/12345/members",
+                ChatMembers {
+                    chat_id: 12345,
+                    filter: MemberFilter::default(),
+                },
+            ),
+            (
+                "tg://chat/12345/messages",
+                ChatMessages {
+                    chat_id: 12345,
+                    range: MessageRange::default(),
+                },
+            ),
+            (
+                "tg://chats/12345/messages",
+                ChatMessages {
+                    chat_id: 12345,
+                    range: MessageRange::default(),
+                },
+            ),
+            (
+                "tg:chat/12345/messages",
+                ChatMessages {
+                    chat_id: 12345,
+                    range: MessageRange::default(),
+                },
+            ),
+            (
+                "tg:chats/12345/messages",
+                ChatMessages {
+                    chat_id: 12345,
+                    range: MessageRange::default(),
+                },
+            ),
+            (
+                "tg://chats/12345/messages/1048576",
+                ChatMessage {
+                    chat_id: 12345,
+                    message_id: 1048576,
+                },
+            ),
+            (
+                "tg:chat/12345/messages/1048576",
+                ChatMessage {
+                    chat_id: 12345,
+                    message_id: 1048576,
+                },
+            ),
+            (
+                "tg://chats/12345/messages/1048576/replies",
+                MessageReplies {
+                    chat_id: 12345,
+                    message_id: 1048576,
+                    range: MessageRange::default(),
+                },
+            ),
+            (
+                "tg:chats/12345/messages/1048576/replies?since=2025-01-01",
+                MessageReplies {
+                    chat_id: 12345,
+                    message_id: 1048576,
+                    range: MessageRange {
+                        since: Some(1735689600),
+                        ..Default::default()
+                    },
+                },
+            ),
             ("tg://user/12345", UserInfo { user_id: 12345 }),
             ("tg://users/12345", UserInfo { user_id: 12345 }),
             ("tg:user/12345", UserInfo { user_id: 12345 }),
             ("tg:users/12345", UserInfo { user_id: 12345 }),
+            ("tg://users/12345/photos", UserPhotos { user_id: 12345 }),
+            ("tg:user/12345/photos", UserPhotos { user_id: 12345 }),
+            ("tg://users/12345/chats", UserChats { user_id: 12345 }),
+            ("tg:user/12345/chats", UserChats { user_id: 12345 }),
+            ("tg://files/42", File { file_id: 42 }),
+            ("tg:file/42", File { file_id: 42 }),
         ];
 
         for (url, expected) in test_cases {
@@ -134,8 +769,14 @@ mod tests {
                 (Chat { chat_id: a }, Chat { chat_id: b }) => {
                     assert_eq!(a, b)
                 }
-                (ChatMembers { chat_id: a }, ChatMembers { chat_id: b }) => assert_eq!(a, b),
-                (ChatMessages { chat_id: a }, ChatMessages { chat_id: b }) => assert_eq!(a, b),
+                (a @ ChatMembers { .. }, b @ ChatMembers { .. }) => assert_eq!(a, b),
+                (a @ ChatMessages { .. }, b @ ChatMessages { .. }) => assert_eq!(a, b),
+                (a @ ChatMessage { .. }, b @ ChatMessage { .. }) => assert_eq!(a, b),
+                (a @ MessageReplies { .. }, b @ MessageReplies { .. }) => assert_eq!(a, b),
+                (a @ UserPhotos { .. }, b @ UserPhotos { .. }) => assert_eq!(a, b),
+                (a @ UserChats { .. }, b @ UserChats { .. }) => assert_eq!(a, b),
+                (a @ File { .. }, b @ File { .. }) => assert_eq!(a, b),
+                (Me, Me) | (Contacts, Contacts) => {}
                 (UserInfo { user_id: a }, UserInfo { user_id: b }) => {
                     assert_eq!(a, b)
                 }
@@ -148,6 +789,17 @@ mod tests {
             ("http://chats/12345", "Unknown scheme"),
             ("tg://chats/not_a_number", "Invalid chat ID"),
             ("tg://users/not_a_number", "Invalid user ID"),
+            ("tg://chats/12345/messages/latest", "Invalid message ID"),
+            ("tg://chats/12345/messages?since=yesterday", "Invalid date"),
+            (
+                "tg://chats/12345/messages?from_id=latest",
+                "Invalid message ID",
+            ),
+            (
+                "tg://chats/12345/messages?after=2025-01-01",
+                "Unknown query parameter",
+            ),
+            ("tg://files/photo.jpg", "Invalid file ID"),
             ("tg://unknown/format", "Unsupported URL format"),
         ];
 
@@ -157,4 +809,394 @@ mod tests {
             assert!(result.unwrap_err().to_string().contains(expected_error));
         }
     }
+
+    #[test]
+    fn test_parse_public_link() {
+        use FetchTarget::*;
+
+        let test_cases = vec![
+            (
+                "https://t.me/durov",
+                PublicChat {
+                    username: "durov".into(),
+                },
+            ),
+            (
+                "http://telegram.me/durov/",
+                PublicChat {
+                    username: "durov".into(),
+                },
+            ),
+            (
+                "https://t.me/s/durov",
+                PublicChat {
+                    username: "durov".into(),
+                },
+            ),
+            (
+                "https://t.me/durov/42",
+                PublicChatMessage {
+                    username: "durov".into(),
+                    message_id: 42 << 20,
+                },
+            ),
+            (
+                "https://t.me/durov/42?single",
+                PublicChatMessage {
+                    username: "durov".into(),
+                    message_id: 42 << 20,
+                },
+            ),
+            (
+                "https://t.me/c/1234567890",
+                Chat {
+                    chat_id: -1001234567890,
+                },
+            ),
+            (
+                "https://t.me/c/1234567890/42",
+                ChatMessage {
+                    chat_id: -1001234567890,
+                    message_id: 42 << 20,
+                },
+            ),
+        ];
+
+        for (url, expected) in test_cases {
+            assert_eq!(parse_resource_url(url).unwrap(), expected, "{url}");
+        }
+
+        let error_cases = vec![
+            ("https://t.me/durov/latest", "Invalid message ID"),
+            ("https://t.me/c/channel/42", "Invalid chat ID"),
+            ("https://t.me/joinchat/AAAA", "Unsupported URL format"),
+            ("https://t.me/+AAAA", "Unsupported URL format"),
+            ("https://example.com/durov", "Unknown scheme"),
+        ];
+
+        for (url, expected_error) in error_cases {
+            let result = parse_resource_url(url);
+            assert!(
+                result.unwrap_err().to_string().contains(expected_error),
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_deep_link() {
+        use FetchTarget::*;
+
+        let test_cases = vec![
+            (
+                "tg://resolve?domain=durov",
+                PublicChat {
+                    username: "durov".into(),
+                },
+            ),
+            (
+                "tg://resolve?domain=durov&post=42",
+                PublicChatMessage {
+                    username: "durov".into(),
+                    message_id: 42 << 20,
+                },
+            ),
+            (
+                "tg:resolve?domain=durov",
+                PublicChat {
+                    username: "durov".into(),
+                },
+            ),
+            (
+                "tg://privatepost?channel=1234567890&post=42",
+                ChatMessage {
+                    chat_id: -1001234567890,
+                    message_id: 42 << 20,
+                },
+            ),
+            ("tg://user?id=12345", UserInfo { user_id: 12345 }),
+        ];
+
+        for (url, expected) in test_cases {
+            assert_eq!(parse_resource_url(url).unwrap(), expected, "{url}");
+        }
+
+        let error_cases = vec![
+            ("tg://resolve", "Missing `domain` parameter"),
+            ("tg://resolve?domain=a/b", "Invalid username"),
+            ("tg://resolve?domain=durov&post=x", "Invalid message ID"),
+            (
+                "tg://privatepost?channel=1234567890",
+                "Missing `post` parameter",
+            ),
+            ("tg://user?id=me", "Invalid user ID"),
+        ];
+
+        for (url, expected_error) in error_cases {
+            let result = parse_resource_url(url);
+            assert!(
+                result.unwrap_err().to_string().contains(expected_error),
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_message_range() {
+        let target = parse_resource_url(
+            "tg://chats/12345/messages?since=2025-01-01&until=2025-02-01T00:00:00Z&from_id=1048576",
+        )
+        .unwrap();
+
+        assert_eq!(
+            target,
+            FetchTarget::ChatMessages {
+                chat_id: 12345,
+                range: MessageRange {
+                    since: Some(1735689600),
+                    until: Some(1738368000),
+                    from_id: Some(1048576),
+                },
+            }
+        );
+
+        let FetchTarget::ChatMessages { range, .. } = target else {
+            unreachable!()
+        };
+        assert!(!range.contains(1735689599));
+        assert!(range.contains(1735689600));
+        assert!(range.contains(1738367999));
+        assert!(!range.contains(1738368000));
+    }
+
+    #[test]
+    fn test_parse_message_order() {
+        assert_eq!("asc".parse::<MessageOrder>().unwrap(), MessageOrder::Asc);
+        assert_eq!("desc".parse::<MessageOrder>().unwrap(), MessageOrder::Desc);
+        assert!("up".parse::<MessageOrder>().is_err());
+    }
+
+    #[test]
+    fn test_parse_member_filter() {
+        assert_eq!(
+            parse_resource_url("tg://chats/12345/members?filter=bots&q=news").unwrap(),
+            FetchTarget::ChatMembers {
+                chat_id: 12345,
+                filter: MemberFilter {
+                    role: MemberRole::Bots,
+                    query: Some("news".to_string()),
+                },
+            }
+        );
+        assert_eq!(
+            parse_resource_url("tg://chats/12345/members?filter=recent").unwrap(),
+            FetchTarget::ChatMembers {
+                chat_id: 12345,
+                filter: MemberFilter::default(),
+            }
+        );
+
+        for (url, error) in [
+            (
+                "tg://chats/12345/members?filter=owners",
+                "Unknown member filter",
+            ),
+            (
+                "tg://chats/12345/members?since=2025-01-01",
+                "Unknown query parameter",
+            ),
+        ] {
+            let err = parse_resource_url(url).unwrap_err().to_string();
+            assert!(err.contains(error), "{url}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_parse_search_url() {
+        use FetchTarget::*;
+
+        let test_cases = vec![
+            (
+                "tg://search?q=hello%20world",
+                SearchMessages {
+                    search: MessageSearch {
+                        query: "hello world".to_string(),
+                        ..Default::default()
+                    },
+                },
+            ),
+            (
+                "tg://search?q=report&filter=document",
+                SearchMessages {
+                    search: MessageSearch {
+                        query: "report".to_string(),
+                        filter: Some(MessageFilter::Document),
+                        ..Default::default()
+                    },
+                },
+            ),
+            (
+                "tg://chats/12345/search?q=hello&sender=777&filter=photo",
+                ChatSearch {
+                    chat_id: 12345,
+                    search: MessageSearch {
+                        query: "hello".to_string(),
+                        sender_id: Some(777),
+                        filter: Some(MessageFilter::Photo),
+                    },
+                },
+            ),
+            (
+                "tg:chat/12345/search?filter=voice",
+                ChatSearch {
+                    chat_id: 12345,
+                    search: MessageSearch {
+                        filter: Some(MessageFilter::Voice),
+                        ..Default::default()
+                    },
+                },
+            ),
+        ];
+
+        for (url, expected) in test_cases {
+            assert_eq!(parse_resource_url(url).unwrap(), expected, "{url}");
+        }
+
+        for (url, error) in [
+            ("tg://search", "Missing `q` parameter"),
+            (
+                "tg://search?q=hello&sender=777",
+                "only supported when searching a chat",
+            ),
+            (
+                "tg://chats/12345/search?filter=sticker",
+                "Unknown message filter",
+            ),
+            ("tg://chats/12345/search?sender=alice", "Invalid sender ID"),
+            (
+                "tg://chats/12345/search?since=2025-01-01",
+                "Unknown query parameter",
+            ),
+        ] {
+            let err = parse_resource_url(url).unwrap_err().to_string();
+            assert!(err.contains(error), "{url}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_parse_media_url() {
+        assert_eq!(
+            parse_resource_url("tg://chats/12345/media/documents").unwrap(),
+            FetchTarget::ChatMedia {
+                chat_id: 12345,
+                filter: MessageFilter::Document,
+            }
+        );
+        assert_eq!(
+            parse_resource_url("tg:chat/12345/media/links").unwrap(),
+            FetchTarget::ChatMedia {
+                chat_id: 12345,
+                filter: MessageFilter::Url,
+            }
+        );
+
+        let err = parse_resource_url("tg://chats/12345/media/stickers").unwrap_err();
+        assert!(err.to_string().contains("Unknown media kind"));
+    }
+
+    #[test]
+    fn test_parse_chat_list_url() {
+        use FetchTarget::*;
+
+        let test_cases = vec![
+            ("tg://chats", Chats { list: None }),
+            ("tg:chats", Chats { list: None }),
+            (
+                "tg://chats?list=main",
+                Chats {
+                    list: Some(ChatList::Main),
+                },
+            ),
+            (
+                "tg://chats?list=archive",
+                Chats {
+                    list: Some(ChatList::Archive),
+                },
+            ),
+            (
+                "tg://chats?list=folder:2",
+                Chats {
+                    list: Some(ChatList::Folder(2)),
+                },
+            ),
+            (
+                "tg://folders/2/chats",
+                Chats {
+                    list: Some(ChatList::Folder(2)),
+                },
+            ),
+            ("tg://folders", Folders),
+            ("tg:folders", Folders),
+        ];
+
+        for (url, expected) in test_cases {
+            assert_eq!(parse_resource_url(url).unwrap(), expected, "{url}");
+        }
+
+        for (url, error) in [
+            ("tg://chats?list=pinned", "Unknown chat list"),
+            ("tg://chats?list=folder:work", "Invalid folder ID"),
+            ("tg://folders/work/chats", "Invalid folder ID"),
+            ("tg://chats?sort=name", "Unknown query parameter"),
+        ] {
+            let err = parse_resource_url(url).unwrap_err().to_string();
+            assert!(err.contains(error), "{url}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_parse_topic_url() {
+        use FetchTarget::*;
+
+        let test_cases = vec![
+            (
+                "tg://chats/-1001234567890/topics",
+                ChatTopics {
+                    chat_id: -1001234567890,
+                },
+            ),
+            (
+                "tg:chat/-1001234567890/topics",
+                ChatTopics {
+                    chat_id: -1001234567890,
+                },
+            ),
+            (
+                "tg://chats/-1001234567890/topics/1048576/messages",
+                ChatTopicMessages {
+                    chat_id: -1001234567890,
+                    topic_id: 1048576,
+                    range: MessageRange::default(),
+                },
+            ),
+            (
+                "tg://chats/-1001234567890/topics/1048576/messages?since=1735689600",
+                ChatTopicMessages {
+                    chat_id: -1001234567890,
+                    topic_id: 1048576,
+                    range: MessageRange {
+                        since: Some(1735689600),
+                        ..Default::default()
+                    },
+                },
+            ),
+        ];
+
+        for (url, expected) in test_cases {
+            assert_eq!(parse_resource_url(url).unwrap(), expected, "{url}");
+        }
+
+        let result = parse_resource_url("tg://chats/-1001234567890/topics/general/messages");
+        assert!(result.unwrap_err().to_string().contains("Invalid topic ID"));
+    }
 }
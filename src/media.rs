@@ -0,0 +1,228 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Storage of downloaded message media in a content-addressed directory.
+//!
+//! Files are named after the SHA-256 hash of their contents, keeping the
+//! extension of the downloaded file, so a file that was shared in several
+//! messages or chats is stored only once.
+
+use miette::{IntoDiagnostic, Result, WrapErr, miette};
+use sha2::{Digest, Sha256};
+use std::{
+    format, fs, io,
+    path::{Path, PathBuf},
+    string::String,
+    vec::Vec,
+};
+use tdlib_rs::{enums::MessageContent, types::File};
+
+/// Limits on which files get downloaded.
+#[derive(Clone, Debug, Default)]
+pub struct MediaLimits {
+    /// The largest file to download, in bytes.
+    pub max_size: Option<u64>,
+    /// The MIME types to download, e.g. `application/pdf` or `image/*`.
+    /// All types are downloaded if this is empty.
+    pub mime_types: Vec<String>,
+}
+
+impl MediaLimits {
+    /// Checks a file against the limits. Files of unknown size or MIME type
+    /// are only checked against what is known about them.
+    pub fn allows(&self, size: u64, mime_type: Option<&str>) -> bool {
+        self.max_size.is_none_or(|max| size <= max)
+            && (self.mime_types.is_empty()
+                || mime_type.is_none_or(|mime_type| {
+                    self.mime_types
+                        .iter()
+                        .any(|pattern| mime_type_matches(pattern, mime_type))
+                }))
+    }
+}
+
+fn mime_type_matches(pattern: &str, mime_type: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(kind) => mime_type
+            .split_once('/')
+            .is_some_and(|(other, _)| other.eq_ignore_ascii_case(kind)),
+        None => pattern.eq_ignore_ascii_case(mime_type),
+    }
+}
+
+/// The name of the directory in the module data directory that avatars are
+/// downloaded into.
+pub const AVATARS_DIR_NAME: &str = "avatars";
+
+/// The size of chat and user avatars to download.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AvatarSize {
+    /// 160x160 pixels.
+    #[default]
+    Small,
+    /// 640x640 pixels.
+    Big,
+}
+
+impl AvatarSize {
+    /// The field of a TDLib `chatPhotoInfo` or `profilePhoto` holding the
+    /// file of this size.
+    pub fn field(self) -> &'static str {
+        match self {
+            AvatarSize::Small => "small",
+            AvatarSize::Big => "big",
+        }
+    }
+}
+
+impl core::str::FromStr for AvatarSize {
+    type Err = miette::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "small" => Ok(AvatarSize::Small),
+            "big" => Ok(AvatarSize::Big),
+            _ => Err(miette!(
+                "Unknown avatar size `{s}`, expected `small` or `big`"
+            )),
+        }
+    }
+}
+
+/// A directory of downloaded files, named by content hash.
+#[derive(Clone, Debug)]
+pub struct MediaStore {
+    dir: PathBuf,
+}
+
+impl MediaStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to create media directory {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    /// Copies a downloaded file into the store, unless it is already there,
+    /// and returns its path in the store.
+    pub fn store(&self, path: &Path) -> Result<PathBuf> {
+        let mut file = fs::File::open(path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to open {}", path.display()))?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut file, &mut hasher).into_diagnostic()?;
+        let hash = hex::encode(hasher.finalize());
+
+        let name = match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) => format!("{hash}.{ext}"),
+            None => hash,
+        };
+        let target = self.dir.join(&name);
+        if !target.exists() {
+            // Copy under a temporary name first, so that an interrupted copy
+            // never leaves a truncated file under a content hash.
+            let partial = self.dir.join(format!(".{name}.partial"));
+            fs::copy(path, &partial)
+                .and_then(|_| fs::rename(&partial, &target))
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to store {}", path.display()))?;
+        }
+        Ok(target)
+    }
+}
+
+/// Returns the file a message carries, e.g. a document or the largest size
+/// of a photo, along with its MIME type if known.
+pub fn message_file(content: &MessageContent) -> Option<(&File, Option<&str>)> {
+    let (file, mime_type) = match content {
+        MessageContent::MessagePhoto(content) => (&content.photo.sizes.last()?.photo, "image/jpeg"),
+        MessageContent::MessageVideo(content) => {
+            (&content.video.video, content.video.mime_type.as_str())
+        }
+        MessageContent::MessageDocument(content) => (
+            &content.document.document,
+            content.document.mime_type.as_str(),
+        ),
+        MessageContent::MessageAudio(content) => {
+            (&content.audio.audio, content.audio.mime_type.as_str())
+        }
+        MessageContent::MessageVoiceNote(content) => (
+            &content.voice_note.voice,
+            content.voice_note.mime_type.as_str(),
+        ),
+        MessageContent::MessageVideoNote(content) => (&content.video_note.video, "video/mp4"),
+        MessageContent::MessageAnimation(content) => (
+            &content.animation.animation,
+            content.animation.mime_type.as_str(),
+        ),
+        _ => return None,
+    };
+    Some((file, Some(mime_type).filter(|s| !s.is_empty())))
+}
+
+/// Returns the size of a file in bytes, or its expected size if the exact
+/// size isn't known yet.
+pub fn file_size(file: &File) -> u64 {
+    let size = if file.size > 0 {
+        file.size
+    } else {
+        file.expected_size
+    };
+    size.max(0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{string::ToString, vec};
+
+    #[test]
+    fn test_media_limits() {
+        let limits = MediaLimits {
+            max_size: Some(1024),
+            mime_types: vec!["image/*".to_string(), "application/pdf".to_string()],
+        };
+        assert!(limits.allows(1024, Some("image/jpeg")));
+        assert!(limits.allows(10, Some("Application/PDF")));
+        assert!(limits.allows(10, None));
+        assert!(!limits.allows(1025, Some("image/jpeg")));
+        assert!(!limits.allows(10, Some("video/mp4")));
+        assert!(MediaLimits::default().allows(u64::MAX, Some("video/mp4")));
+    }
+
+    #[test]
+    fn test_parse_avatar_size() {
+        assert_eq!("small".parse::<AvatarSize>().unwrap(), AvatarSize::Small);
+        assert_eq!("big".parse::<AvatarSize>().unwrap(), AvatarSize::Big);
+        assert!("huge".parse::<AvatarSize>().is_err());
+    }
+
+    #[test]
+    fn test_media_store() {
+        let dir =
+            std::env::temp_dir().join(format!("asimov-telegram-media-{}", std::process::id()));
+        let store = MediaStore::new(dir.join("store")).unwrap();
+
+        let source = dir.join("report.pdf");
+        fs::write(&source, b"hello").unwrap();
+
+        let path = store.store(&source).unwrap();
+        assert_eq!(
+            path.file_name().unwrap(),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824.pdf"
+        );
+        assert_eq!(fs::read(&path).unwrap(), b"hello");
+        assert_eq!(store.store(&source).unwrap(), path);
+
+        // The same contents under another name are stored only once:
+        let other = dir.join("copy.pdf");
+        fs::write(&other, b"hello").unwrap();
+        assert_eq!(store.store(&other).unwrap(), path);
+
+        // Other contents get another name:
+        fs::write(&other, b"other").unwrap();
+        assert_ne!(store.store(&other).unwrap(), path);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
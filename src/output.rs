@@ -0,0 +1,273 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Output of records as JSON Lines, JSON, JSON-LD, Turtle or N-Triples.
+
+use crate::{
+    jq::{JsonFilter, JsonFilterError},
+    rdf,
+};
+use miette::{IntoDiagnostic, Result, miette};
+use oxrdfio::{RdfFormat, RdfSerializer, WriterQuadSerializer};
+use serde_json::Value;
+use std::{format, io::Write};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One JSON value per line.
+    #[default]
+    Jsonl,
+    /// A single JSON array.
+    Json,
+    /// A single JSON-LD document, describing the same graph as the RDF formats.
+    Jsonld,
+    /// RDF in Turtle syntax.
+    Turtle,
+    /// RDF in N-Triples syntax.
+    Ntriples,
+}
+
+impl OutputFormat {
+    pub const SUPPORTED: &[&str] = &["jsonl", "json", "jsonld", "turtle", "ntriples"];
+}
+
+impl core::str::FromStr for OutputFormat {
+    type Err = miette::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        use OutputFormat::*;
+        match s {
+            "jsonl" => Ok(Jsonl),
+            "json" => Ok(Json),
+            "jsonld" => Ok(Jsonld),
+            "turtle" => Ok(Turtle),
+            "ntriples" => Ok(Ntriples),
+            _ => Err(miette!(
+                "Unknown output format `{s}`, expected one of: {}",
+                Self::SUPPORTED.join(", ")
+            )),
+        }
+    }
+}
+
+impl core::fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use OutputFormat::*;
+        match self {
+            Jsonl => write!(f, "jsonl"),
+            Json => write!(f, "json"),
+            Jsonld => write!(f, "jsonld"),
+            Turtle => write!(f, "turtle"),
+            Ntriples => write!(f, "ntriples"),
+        }
+    }
+}
+
+enum Sink<W: Write> {
+    Lines(W),
+    Array { writer: W, empty: bool },
+    Graph { writer: W, empty: bool },
+    Rdf(WriterQuadSerializer<W>),
+}
+
+/// Writes records in the requested output format.
+///
+/// JSON formats write records through the jq filter, while RDF formats,
+/// including JSON-LD, map the unfiltered TDLib objects with [`rdf::triples`].
+///
+/// Formats that produce a single document (`json`, `jsonld`, `turtle`) are
+/// streamed, so [`Output::finish`] must be called to close the document.
+pub struct Output<'a, W: Write> {
+    filter: &'a JsonFilter,
+    sink: Sink<W>,
+}
+
+impl<'a, W: Write> Output<'a, W> {
+    pub fn new(format: OutputFormat, filter: &'a JsonFilter, writer: W) -> Result<Self> {
+        use OutputFormat::*;
+        let sink = match format {
+            Jsonl => Sink::Lines(writer),
+            Json => Sink::Array {
+                writer,
+                empty: true,
+            },
+            Jsonld => Sink::Graph {
+                writer,
+                empty: true,
+            },
+            Turtle => {
+                let mut serializer = RdfSerializer::from_format(RdfFormat::Turtle);
+                for (prefix, namespace) in rdf::PREFIXES {
+                    serializer = serializer
+                        .with_prefix(*prefix, *namespace)
+                        .into_diagnostic()?;
+                }
+                Sink::Rdf(serializer.for_writer(writer))
+            }
+            Ntriples => {
+                Sink::Rdf(RdfSerializer::from_format(RdfFormat::NTriples).for_writer(writer))
+            }
+        };
+        Ok(Self { filter, sink })
+    }
+
+    /// Writes out a record.
+    ///
+    /// Records the filter rejects are skipped; filter failures are logged.
+    pub fn write(&mut self, record: Value) -> Result<()> {
+        match self.sink {
+            Sink::Rdf(ref mut serializer) => {
+                for triple in rdf::triples(&record) {
+                    serializer.serialize_triple(&triple).into_diagnostic()?;
+                }
+                return Ok(());
+            }
+            Sink::Graph {
+                ref mut writer,
+                ref mut empty,
+            } => {
+                for node in rdf::json_ld_nodes(&rdf::triples(&record)) {
+                    if *empty {
+                        let context = rdf::json_ld_context();
+                        writeln!(writer, "{{\"@context\":{context},\"@graph\":[")
+                            .into_diagnostic()?;
+                    } else {
+                        writeln!(writer, ",").into_diagnostic()?;
+                    }
+                    *empty = false;
+                    write!(writer, "{node}").into_diagnostic()?;
+                }
+                return Ok(());
+            }
+            Sink::Lines(_) | Sink::Array { .. } => (),
+        }
+
+        let record = match self.filter.filter_json(record) {
+            Ok(filtered) => filtered,
+            Err(JsonFilterError::NoOutput) => return Ok(()),
+            Err(err) => {
+                tracing::error!(?err, "Filter failed");
+                return Ok(());
+            }
+        };
+
+        match self.sink {
+            Sink::Lines(ref mut writer) => writeln!(writer, "{record}").into_diagnostic(),
+            Sink::Array {
+                ref mut writer,
+                ref mut empty,
+            } => {
+                let sep = if *empty { "[\n" } else { ",\n" };
+                *empty = false;
+                write!(writer, "{sep}{record}").into_diagnostic()
+            }
+            Sink::Graph { .. } | Sink::Rdf(_) => unreachable!("handled above"),
+        }
+    }
+
+    /// Closes any open document and flushes the output.
+    pub fn finish(self) -> Result<W> {
+        let mut writer = match self.sink {
+            Sink::Lines(writer) => writer,
+            Sink::Array { mut writer, empty } => {
+                writeln!(writer, "{}", if empty { "[]" } else { "\n]" }).into_diagnostic()?;
+                writer
+            }
+            Sink::Graph { mut writer, empty } => {
+                if empty {
+                    let context = rdf::json_ld_context();
+                    writeln!(writer, "{{\"@context\":{context},\"@graph\":[]}}")
+                        .into_diagnostic()?;
+                } else {
+                    writeln!(writer, "\n]}}").into_diagnostic()?;
+                }
+                writer
+            }
+            Sink::Rdf(serializer) => serializer.finish().into_diagnostic()?,
+        };
+        writer.flush().into_diagnostic()?;
+        Ok(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        string::{String, ToString as _},
+        vec::Vec,
+    };
+
+    #[test]
+    fn test_parse_output_format() {
+        for name in OutputFormat::SUPPORTED {
+            let format: OutputFormat = name.parse().unwrap();
+            assert_eq!(format.to_string(), *name);
+        }
+
+        let err = "xml".parse::<OutputFormat>().unwrap_err().to_string();
+        assert!(err.contains("Unknown output format"));
+        assert!(err.contains("jsonl, json, jsonld, turtle, ntriples"));
+    }
+
+    #[test]
+    fn test_write_json_array() {
+        let filter: JsonFilter = ".".parse().unwrap();
+        let mut output = Output::new(OutputFormat::Json, &filter, Vec::new()).unwrap();
+        output.write(serde_json::json!({"id": 1})).unwrap();
+        output.write(serde_json::json!({"id": 2})).unwrap();
+        let bytes = output.finish().unwrap();
+
+        let parsed: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(parsed, serde_json::json!([{"id": 1}, {"id": 2}]));
+
+        let output = Output::new(OutputFormat::Json, &filter, Vec::new()).unwrap();
+        let bytes = output.finish().unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), "[]\n");
+    }
+
+    #[test]
+    fn test_write_ntriples() {
+        let filter: JsonFilter = ".".parse().unwrap();
+        let mut output = Output::new(OutputFormat::Ntriples, &filter, Vec::new()).unwrap();
+        output
+            .write(serde_json::json!({"@type": "chat", "@extra": "1", "id": 5, "tags": ["a", "b"]}))
+            .unwrap();
+        let text = String::from_utf8(output.finish().unwrap()).unwrap();
+
+        assert_eq!(text.lines().count(), 4);
+        assert!(text.contains("<https://asimov.so/ns/telegram#chat>"));
+        assert!(text.contains("<https://asimov.so/ns/telegram#id> \"5\"^^"));
+        assert!(!text.contains("extra"));
+    }
+
+    #[test]
+    fn test_write_jsonld() {
+        // The filter doesn't apply to RDF formats, JSON-LD included.
+        let filter: JsonFilter = "empty".parse().unwrap();
+        let mut output = Output::new(OutputFormat::Jsonld, &filter, Vec::new()).unwrap();
+        output
+            .write(serde_json::json!({
+                "@type": "chat",
+                "id": 5,
+                "type": {"@type": "chatTypeBasicGroup"},
+                "title": "Friends",
+            }))
+            .unwrap();
+        let parsed: Value = serde_json::from_slice(&output.finish().unwrap()).unwrap();
+
+        assert_eq!(parsed["@context"]["schema"], rdf::SCHEMA);
+        assert_eq!(parsed["@graph"][0]["@id"], "tg://chats/5");
+        assert_eq!(
+            parsed["@graph"][0]["@type"],
+            serde_json::json!(["schema:Conversation"])
+        );
+        assert_eq!(
+            parsed["@graph"][0]["schema:name"],
+            serde_json::json!(["Friends"])
+        );
+
+        let output = Output::new(OutputFormat::Jsonld, &filter, Vec::new()).unwrap();
+        let parsed: Value = serde_json::from_slice(&output.finish().unwrap()).unwrap();
+        assert_eq!(parsed["@graph"], serde_json::json!([]));
+    }
+}
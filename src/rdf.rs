@@ -0,0 +1,607 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Mapping of TDLib objects onto RDF triples.
+//!
+//! Chats, users and messages are identified by the same `tg:` URLs that
+//! [`parse_resource_url`](crate::parse_resource_url) accepts, and are
+//! described with the [schema.org](https://schema.org) vocabulary.
+//! Other objects fall back to a generic mapping using [`VOCAB`].
+
+use crate::date::format_datetime;
+use oxrdf::{
+    BlankNode, Literal, NamedNode, Subject, Term, Triple,
+    vocab::{rdf, xsd},
+};
+use serde_json::{Map, Value};
+use std::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// The vocabulary namespace for Telegram-specific properties.
+pub const VOCAB: &str = "https://asimov.so/ns/telegram#";
+
+/// The schema.org namespace.
+pub const SCHEMA: &str = "https://schema.org/";
+
+/// The prefixes used for compact IRIs in Turtle and JSON-LD output.
+pub const PREFIXES: &[(&str, &str)] = &[
+    ("schema", SCHEMA),
+    ("tg", VOCAB),
+    ("rdf", "http://www.w3.org/1999/02/22-rdf-syntax-ns#"),
+    ("xsd", "http://www.w3.org/2001/XMLSchema#"),
+];
+
+pub fn chat_iri(chat_id: i64) -> NamedNode {
+    NamedNode::new_unchecked(format!("tg://chats/{chat_id}"))
+}
+
+pub fn user_iri(user_id: i64) -> NamedNode {
+    NamedNode::new_unchecked(format!("tg://users/{user_id}"))
+}
+
+pub fn message_iri(chat_id: i64, message_id: i64) -> NamedNode {
+    NamedNode::new_unchecked(format!("tg://chats/{chat_id}/messages/{message_id}"))
+}
+
+/// Maps a TDLib object, as serialized by the client, onto triples.
+///
+/// The object kind is taken from its `@type`; objects without a dedicated
+/// mapping, or lacking the fields that identify them, are described
+/// generically.
+pub fn triples(value: &Value) -> Vec<Triple> {
+    let mut triples = Vec::new();
+
+    let mapped = match value.get("@type").and_then(Value::as_str) {
+        Some("chat") => map_chat(value, &mut triples),
+        Some("user") => map_user(value, &mut triples),
+        Some("chatMember") => map_chat_member(value, &mut triples),
+        Some("message") => map_message(value, &mut triples),
+        _ => None,
+    };
+
+    if mapped.is_none() {
+        triples.clear();
+        map_json(value, &mut triples);
+    }
+
+    triples
+}
+
+pub fn map_chat(chat: &Value, triples: &mut Vec<Triple>) -> Option<()> {
+    let id = chat.get("id")?.as_i64()?;
+    let chat_type = chat.get("type")?;
+    let subject = chat_iri(id);
+
+    let kind = match chat_type.get("@type")?.as_str()? {
+        "chatTypePrivate" => "private",
+        "chatTypeBasicGroup" => "basicGroup",
+        "chatTypeSupergroup" if bool_field(chat_type, "is_channel") => "channel",
+        "chatTypeSupergroup" => "supergroup",
+        "chatTypeSecret" => "secret",
+        _ => return None,
+    };
+
+    push(triples, &subject, rdf::TYPE, schema("Conversation"));
+    push(triples, &subject, schema("identifier"), Literal::from(id));
+    push(triples, &subject, vocab("chatType"), Literal::from(kind));
+    if let Some(title) = str_field(chat, "title") {
+        push(triples, &subject, schema("name"), Literal::from(title));
+    }
+    if kind == "private"
+        && let Some(user_id) = chat_type.get("user_id").and_then(Value::as_i64)
+    {
+        push(triples, &subject, schema("about"), user_iri(user_id));
+    }
+    Some(())
+}
+
+pub fn map_user(user: &Value, triples: &mut Vec<Triple>) -> Option<()> {
+    let id = user.get("id")?.as_i64()?;
+    let subject = user_iri(id);
+
+    let first_name = str_field(user, "first_name");
+    let last_name = str_field(user, "last_name");
+    let name = [first_name, last_name]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    push(triples, &subject, rdf::TYPE, schema("Person"));
+    push(triples, &subject, schema("identifier"), Literal::from(id));
+    if !name.is_empty() {
+        push(triples, &subject, schema("name"), Literal::from(name));
+    }
+    if let Some(first_name) = first_name {
+        push(
+            triples,
+            &subject,
+            schema("givenName"),
+            Literal::from(first_name),
+        );
+    }
+    if let Some(last_name) = last_name {
+        push(
+            triples,
+            &subject,
+            schema("familyName"),
+            Literal::from(last_name),
+        );
+    }
+    if let Some(phone) = str_field(user, "phone_number") {
+        let phone = Literal::from(format!("+{phone}"));
+        push(triples, &subject, schema("telephone"), phone);
+    }
+    let usernames = user
+        .pointer("/usernames/active_usernames")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str);
+    for username in usernames {
+        push(
+            triples,
+            &subject,
+            schema("alternateName"),
+            Literal::from(username),
+        );
+        let profile = NamedNode::new_unchecked(format!("https://t.me/{username}"));
+        push(triples, &subject, schema("sameAs"), profile);
+    }
+    if user.pointer("/type/@type").and_then(Value::as_str) == Some("userTypeBot") {
+        push(triples, &subject, vocab("isBot"), Literal::from(true));
+    }
+    Some(())
+}
+
+/// Maps a chat member. Without a `chat_id`, as added by the client, the
+/// membership itself can't be stated, so only the role is described.
+pub fn map_chat_member(member: &Value, triples: &mut Vec<Triple>) -> Option<()> {
+    let member_iri = sender_iri(member.get("member_id")?)?;
+    let status = member.pointer("/status/@type")?.as_str()?;
+    let role = BlankNode::default();
+
+    if let Some(chat_id) = member.get("chat_id").and_then(Value::as_i64) {
+        push(triples, &chat_iri(chat_id), schema("member"), role.clone());
+        push(triples, &member_iri, schema("memberOf"), chat_iri(chat_id));
+    }
+    push(triples, &role, rdf::TYPE, schema("OrganizationRole"));
+    push(triples, &role, schema("member"), member_iri);
+
+    // e.g. `chatMemberStatusAdministrator` => `administrator`
+    if let Some(status) = status.strip_prefix("chatMemberStatus") {
+        let mut chars = status.chars();
+        let role_name: String = chars
+            .next()
+            .map(|c| c.to_ascii_lowercase())
+            .into_iter()
+            .chain(chars)
+            .collect();
+        push(triples, &role, schema("roleName"), Literal::from(role_name));
+    }
+    if let Some(joined) = timestamp_field(member, "joined_chat_date") {
+        push(triples, &role, schema("startDate"), datetime(joined));
+    }
+    if let Some(inviter) = member
+        .get("inviter_user_id")
+        .and_then(Value::as_i64)
+        .filter(|id| *id != 0)
+    {
+        push(triples, &role, vocab("invitedBy"), user_iri(inviter));
+    }
+    Some(())
+}
+
+pub fn map_message(msg: &Value, triples: &mut Vec<Triple>) -> Option<()> {
+    let id = msg.get("id")?.as_i64()?;
+    let chat_id = msg.get("chat_id")?.as_i64()?;
+    let sender = sender_iri(msg.get("sender_id")?)?;
+    let date = timestamp_field(msg, "date")?;
+    let subject = message_iri(chat_id, id);
+
+    push(triples, &subject, rdf::TYPE, schema("Message"));
+    push(triples, &subject, schema("identifier"), Literal::from(id));
+    push(triples, &subject, schema("isPartOf"), chat_iri(chat_id));
+    push(triples, &subject, schema("sender"), sender.clone());
+    push(triples, &subject, schema("author"), sender);
+    push(triples, &subject, schema("dateSent"), datetime(date));
+    push(triples, &subject, schema("dateCreated"), datetime(date));
+    if let Some(edited) = timestamp_field(msg, "edit_date") {
+        push(triples, &subject, schema("dateModified"), datetime(edited));
+    }
+
+    // The text of text messages, or the caption of media messages.
+    let text = msg
+        .pointer("/content/text/text")
+        .or_else(|| msg.pointer("/content/caption/text"))
+        .and_then(Value::as_str)
+        .filter(|text| !text.is_empty());
+    if let Some(text) = text {
+        push(triples, &subject, schema("text"), Literal::from(text));
+    }
+
+    if let Some(reply) = msg
+        .get("reply_to")
+        .filter(|reply| reply.get("@type").and_then(Value::as_str) == Some("messageReplyToMessage"))
+        && let Some(message_id) = reply.get("message_id").and_then(Value::as_i64)
+    {
+        let reply_chat_id = reply
+            .get("chat_id")
+            .and_then(Value::as_i64)
+            .filter(|id| *id != 0)
+            .unwrap_or(chat_id);
+        let parent = message_iri(reply_chat_id, message_id);
+        push(triples, &subject, vocab("replyTo"), parent);
+    }
+
+    if let Some(link) = msg
+        .get("link")
+        .and_then(Value::as_str)
+        .and_then(|link| NamedNode::new(link).ok())
+    {
+        push(triples, &subject, schema("url"), link);
+    }
+    Some(())
+}
+
+/// Describes an arbitrary JSON record as a blank node, using the record keys
+/// as properties in the [`VOCAB`] namespace. Returns the node for the record.
+pub fn map_json(value: &Value, triples: &mut Vec<Triple>) -> Term {
+    match value {
+        Value::Null => Literal::new_simple_literal("").into(),
+        Value::Bool(b) => Literal::from(*b).into(),
+        Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) => Literal::from(i).into(),
+            (None, Some(f)) => Literal::from(f).into(),
+            (None, None) => Literal::new_simple_literal(n.to_string()).into(),
+        },
+        Value::String(s) => Literal::new_simple_literal(s.as_str()).into(),
+        Value::Array(items) => {
+            // Arrays aren't ordered in RDF; callers attach each item separately.
+            let node = BlankNode::default();
+            for item in items {
+                let object = map_json(item, triples);
+                push(triples, &node, rdf::VALUE, object);
+            }
+            node.into()
+        }
+        Value::Object(map) => {
+            let node = BlankNode::default();
+            for (key, value) in map {
+                if key == "@type" {
+                    if let Some(class) = value.as_str().and_then(vocab_term) {
+                        push(triples, &node, rdf::TYPE, class);
+                    }
+                    continue;
+                }
+                let Some(predicate) = vocab_term(key) else {
+                    continue; // skip other keywords like `@extra`
+                };
+                let items: &[Value] = match value {
+                    Value::Array(items) => items.as_slice(),
+                    Value::Null => &[],
+                    value => core::slice::from_ref(value),
+                };
+                for item in items {
+                    let object = map_json(item, triples);
+                    push(triples, &node, predicate.clone(), object);
+                }
+            }
+            node.into()
+        }
+    }
+}
+
+/// Returns the JSON-LD context declaring [`PREFIXES`], for use with
+/// [`json_ld_nodes`].
+pub fn json_ld_context() -> Value {
+    let prefixes = PREFIXES
+        .iter()
+        .map(|(prefix, namespace)| (prefix.to_string(), Value::from(*namespace)));
+    Value::Object(prefixes.collect())
+}
+
+/// Describes triples as JSON-LD node objects, one per subject, in the order
+/// the subjects first appear.
+pub fn json_ld_nodes(triples: &[Triple]) -> Vec<Value> {
+    let mut nodes: Vec<(&Subject, Map<String, Value>)> = Vec::new();
+    for triple in triples {
+        let index = match nodes.iter().position(|(s, _)| *s == &triple.subject) {
+            Some(index) => index,
+            None => {
+                nodes.push((&triple.subject, Map::new()));
+                nodes.len() - 1
+            }
+        };
+
+        let (key, value) = match triple.object {
+            Term::NamedNode(ref class) if triple.predicate.as_ref() == rdf::TYPE => {
+                ("@type".into(), compact_iri(class.as_str()).into())
+            }
+            ref object => (
+                compact_iri(triple.predicate.as_str()),
+                json_ld_object(object),
+            ),
+        };
+        if let Value::Array(values) = nodes[index]
+            .1
+            .entry(key)
+            .or_insert(Value::Array(Vec::new()))
+        {
+            values.push(value);
+        }
+    }
+
+    nodes
+        .into_iter()
+        .map(|(subject, mut node)| {
+            let id = match subject {
+                Subject::NamedNode(node) => node.as_str().to_string(),
+                Subject::BlankNode(node) => format!("_:{}", node.as_str()),
+            };
+            node.insert("@id".into(), id.into());
+            Value::Object(node)
+        })
+        .collect()
+}
+
+fn json_ld_object(object: &Term) -> Value {
+    match object {
+        Term::NamedNode(node) => serde_json::json!({ "@id": node.as_str() }),
+        Term::BlankNode(node) => serde_json::json!({ "@id": format!("_:{}", node.as_str()) }),
+        Term::Literal(literal) => match literal.language() {
+            Some(language) => {
+                serde_json::json!({ "@value": literal.value(), "@language": language })
+            }
+            None if literal.datatype() == xsd::STRING => literal.value().into(),
+            None => serde_json::json!({
+                "@value": literal.value(),
+                "@type": compact_iri(literal.datatype().as_str()),
+            }),
+        },
+    }
+}
+
+/// Shortens an IRI with the first of [`PREFIXES`] it starts with.
+fn compact_iri(iri: &str) -> String {
+    PREFIXES
+        .iter()
+        .find_map(|(prefix, namespace)| {
+            iri.strip_prefix(namespace)
+                .filter(|name| !name.is_empty())
+                .map(|name| format!("{prefix}:{name}"))
+        })
+        .unwrap_or_else(|| iri.to_string())
+}
+
+fn push<S: Clone + Into<Subject>>(
+    triples: &mut Vec<Triple>,
+    subject: &S,
+    predicate: impl Into<NamedNode>,
+    object: impl Into<Term>,
+) {
+    triples.push(Triple::new(subject.clone(), predicate, object));
+}
+
+fn schema(name: &str) -> NamedNode {
+    NamedNode::new_unchecked(format!("{SCHEMA}{name}"))
+}
+
+fn vocab(name: &str) -> NamedNode {
+    NamedNode::new_unchecked(format!("{VOCAB}{name}"))
+}
+
+fn vocab_term(name: &str) -> Option<NamedNode> {
+    if name.starts_with('@') {
+        return None;
+    }
+    NamedNode::new(format!("{VOCAB}{name}")).ok()
+}
+
+/// Formats a Unix timestamp as an `xsd:dateTime` literal in UTC.
+fn datetime(timestamp: i64) -> Literal {
+    Literal::new_typed_literal(format_datetime(timestamp), xsd::DATE_TIME)
+}
+
+fn sender_iri(sender: &Value) -> Option<NamedNode> {
+    match sender.get("@type")?.as_str()? {
+        "messageSenderUser" => Some(user_iri(sender.get("user_id")?.as_i64()?)),
+        "messageSenderChat" => Some(chat_iri(sender.get("chat_id")?.as_i64()?)),
+        _ => None,
+    }
+}
+
+/// Returns a string field, unless it is absent or empty.
+fn str_field<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+}
+
+fn bool_field(value: &Value, key: &str) -> bool {
+    value.get(key).and_then(Value::as_bool).unwrap_or_default()
+}
+
+/// Returns a timestamp field, unless it is absent or zero.
+fn timestamp_field(value: &Value, key: &str) -> Option<i64> {
+    value
+        .get(key)
+        .and_then(Value::as_i64)
+        .filter(|timestamp| *timestamp > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generic_fallback() {
+        let record = serde_json::json!({"@type": "chat", "id": 5});
+        let triples = triples(&record);
+        assert_eq!(triples.len(), 2);
+        assert!(triples.iter().all(|t| t.subject.is_blank_node()));
+    }
+
+    fn fixture(name: &str) -> Vec<Triple> {
+        let json = match name {
+            "chat" => include_str!("jq/fixtures/chat.json"),
+            "user" => include_str!("jq/fixtures/user.json"),
+            "chat_member" => include_str!("jq/fixtures/chat_member.json"),
+            "message" => include_str!("jq/fixtures/message.json"),
+            _ => unreachable!(),
+        };
+        triples(&serde_json::from_str(json).unwrap())
+    }
+
+    fn contains(
+        triples: &[Triple],
+        subject: impl Into<Subject>,
+        predicate: NamedNode,
+        object: impl Into<Term>,
+    ) -> bool {
+        triples.contains(&Triple::new(subject, predicate, object))
+    }
+
+    #[test]
+    fn test_map_chat() {
+        let triples = fixture("chat");
+        let chat = chat_iri(123456789);
+        assert!(contains(
+            &triples,
+            chat.clone(),
+            rdf::TYPE.into(),
+            schema("Conversation")
+        ));
+        assert!(contains(
+            &triples,
+            chat.clone(),
+            schema("name"),
+            Literal::from("Ada Lovelace")
+        ));
+        assert!(contains(
+            &triples,
+            chat,
+            schema("about"),
+            user_iri(123456789)
+        ));
+    }
+
+    #[test]
+    fn test_map_user() {
+        let triples = fixture("user");
+        let user = user_iri(123456789);
+        assert!(contains(
+            &triples,
+            user.clone(),
+            rdf::TYPE.into(),
+            schema("Person")
+        ));
+        assert!(contains(
+            &triples,
+            user.clone(),
+            schema("name"),
+            Literal::from("Ada Lovelace")
+        ));
+        assert!(contains(
+            &triples,
+            user,
+            schema("sameAs"),
+            NamedNode::new_unchecked("https://t.me/ada")
+        ));
+    }
+
+    #[test]
+    fn test_map_chat_member() {
+        let triples = fixture("chat_member");
+        let (user, chat) = (user_iri(123456789), chat_iri(-1001234567890));
+        assert!(contains(&triples, user.clone(), schema("memberOf"), chat));
+
+        let role = triples
+            .iter()
+            .find(|t| t.predicate == schema("member") && t.object == Term::from(user.clone()))
+            .map(|t| t.subject.clone())
+            .unwrap();
+        assert!(contains(
+            &triples,
+            role.clone(),
+            schema("roleName"),
+            Literal::from("administrator")
+        ));
+        assert!(contains(
+            &triples,
+            role,
+            schema("startDate"),
+            datetime(1704067200)
+        ));
+    }
+
+    #[test]
+    fn test_map_message() {
+        let triples = fixture("message");
+        let msg = message_iri(-1001234567890, 2097152);
+        let sender = chat_iri(-1001234567890);
+        assert!(contains(
+            &triples,
+            msg.clone(),
+            rdf::TYPE.into(),
+            schema("Message")
+        ));
+        assert!(contains(
+            &triples,
+            msg.clone(),
+            schema("author"),
+            sender.clone()
+        ));
+        assert!(contains(&triples, msg.clone(), schema("isPartOf"), sender));
+        assert!(contains(
+            &triples,
+            msg.clone(),
+            schema("dateCreated"),
+            Literal::new_typed_literal("2025-01-01T00:00:00Z", xsd::DATE_TIME)
+        ));
+        assert!(contains(
+            &triples,
+            msg.clone(),
+            schema("text"),
+            Literal::from("Fireworks")
+        ));
+        assert!(contains(
+            &triples,
+            msg.clone(),
+            vocab("replyTo"),
+            message_iri(-1001234567890, 1048576)
+        ));
+        assert!(contains(
+            &triples,
+            msg,
+            schema("url"),
+            NamedNode::new_unchecked("https://t.me/c/1234567890/2")
+        ));
+    }
+
+    #[test]
+    fn test_json_ld_nodes() {
+        let nodes = json_ld_nodes(&fixture("message"));
+        assert_eq!(nodes.len(), 1);
+
+        let node = &nodes[0];
+        assert_eq!(node["@id"], "tg://chats/-1001234567890/messages/2097152");
+        assert_eq!(node["@type"], serde_json::json!(["schema:Message"]));
+        assert_eq!(
+            node["schema:author"],
+            serde_json::json!([{ "@id": "tg://chats/-1001234567890" }])
+        );
+        assert_eq!(node["schema:text"], serde_json::json!(["Fireworks"]));
+        assert_eq!(
+            node["schema:dateCreated"],
+            serde_json::json!([{ "@value": "2025-01-01T00:00:00Z", "@type": "xsd:dateTime" }])
+        );
+
+        let context = json_ld_context();
+        assert_eq!(context["schema"], SCHEMA);
+        assert_eq!(context["tg"], VOCAB);
+    }
+}
@@ -1,5 +1,11 @@
 // This is free and unencumbered software released into the public domain.
 
+use crate::{
+    FetchTarget, MemberFilter, MemberRole, MessageFilter, MessageOrder, MessageRange,
+    MessageSearch,
+    media::{self, AvatarSize, MediaLimits, MediaStore},
+};
+use futures::{StreamExt as _, stream::BoxStream};
 use miette::{IntoDiagnostic, Result, WrapErr, bail, miette};
 use serde_json::Value;
 use std::{
@@ -30,12 +36,55 @@ enum State {
     AwaitingCode,
     AwaitingPassword {
         hint: String,
+        recovery_email: Option<String>,
+    },
+    AwaitingOtherDeviceConfirmation {
+        link: String,
+    },
+    AwaitingEmailAddress,
+    AwaitingEmailCode {
+        email_pattern: String,
+    },
+    AwaitingRegistration {
+        terms_of_service: String,
     },
+    LoggingOut,
+    Closing,
+    Closed,
     Authorized {
         chats: BTreeMap<i64, Value>,
     },
 }
 
+/// A step of logging in, as required by Telegram.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuthStep {
+    PhoneNumber,
+    Code,
+    /// The 2FA password. `recovery_email` is set if the password can be
+    /// recovered by email, and holds the pattern of the address once a
+    /// recovery code has been sent to it.
+    Password {
+        hint: String,
+        recovery_email: Option<String>,
+    },
+    /// A QR code login waiting to be confirmed with the given link.
+    OtherDeviceConfirmation {
+        link: String,
+    },
+    EmailAddress,
+    EmailCode {
+        email_pattern: String,
+    },
+    /// A new account, to be registered after accepting these terms.
+    Registration {
+        terms_of_service: String,
+    },
+    Ready,
+    /// The client is logging out or closed, and can't log in.
+    Closed,
+}
+
 #[derive(Clone)]
 pub struct Config {
     pub api_id: String,
@@ -44,7 +93,9 @@ pub struct Config {
     pub encryption_key: String,
 }
 
-struct TdHandle(i32);
+/// A TDLib client ID, along with the client's state, which tells whether
+/// the client has already been closed.
+struct TdHandle(i32, Arc<RwLock<State>>);
 
 // I *think* this is ok? If not will just have to start a second worker thread
 // that does all the `td_json_client_send`.
@@ -53,6 +104,11 @@ unsafe impl Sync for TdHandle {}
 
 impl Drop for TdHandle {
     fn drop(&mut self) {
+        // A closed client, e.g. after logging out, no longer answers requests.
+        if self.1.try_read().is_ok_and(|state| *state == State::Closed) {
+            return;
+        }
+
         tracing::trace!("Closing TdLib handle");
         tokio::task::block_in_place(|| {
             tokio::runtime::Handle::current()
@@ -65,6 +121,8 @@ impl Drop for TdHandle {
 pub struct Client {
     config: Config,
     state: Arc<RwLock<State>>,
+    /// The IDs of the user's chat folders, once TDLib has sent them.
+    folder_ids: Arc<RwLock<Option<Vec<i32>>>>,
     handle: Arc<TdHandle>,
 }
 
@@ -72,13 +130,15 @@ impl Client {
     pub fn new(config: Config) -> Result<Self> {
         unsafe { td_set_log_verbosity_level(0) };
 
-        let handle = tdlib_rs::create_client();
-        let handle = Arc::new(TdHandle(handle));
-
         let state = Arc::new(RwLock::new(State::default()));
+        let folder_ids = Arc::new(RwLock::new(None));
+
+        let handle = tdlib_rs::create_client();
+        let handle = Arc::new(TdHandle(handle, state.clone()));
 
         let _receiver_handle = tokio::task::spawn_blocking({
             let state = state.clone();
+            let folder_ids = folder_ids.clone();
 
             move || {
                 loop {
@@ -102,6 +162,9 @@ impl Client {
                             WaitPassword(x) => {
                                 *state.blocking_write() = State::AwaitingPassword {
                                     hint: x.password_hint,
+                                    recovery_email: x
+                                        .has_recovery_email_address
+                                        .then_some(x.recovery_email_address_pattern),
                                 }
                             }
                             Ready => {
@@ -109,13 +172,29 @@ impl Client {
                                     chats: BTreeMap::new(),
                                 }
                             }
-                            Closed => break,
-                            WaitEmailAddress(_)
-                            | WaitEmailCode(_)
-                            | WaitOtherDeviceConfirmation(_)
-                            | WaitRegistration(_)
-                            | LoggingOut
-                            | Closing => (), // ignore
+                            WaitOtherDeviceConfirmation(x) => {
+                                *state.blocking_write() =
+                                    State::AwaitingOtherDeviceConfirmation { link: x.link }
+                            }
+                            WaitEmailAddress(_) => {
+                                *state.blocking_write() = State::AwaitingEmailAddress
+                            }
+                            WaitEmailCode(x) => {
+                                *state.blocking_write() = State::AwaitingEmailCode {
+                                    email_pattern: x.code_info.email_address_pattern,
+                                }
+                            }
+                            WaitRegistration(x) => {
+                                *state.blocking_write() = State::AwaitingRegistration {
+                                    terms_of_service: x.terms_of_service.text.text,
+                                }
+                            }
+                            LoggingOut => *state.blocking_write() = State::LoggingOut,
+                            Closing => *state.blocking_write() = State::Closing,
+                            Closed => {
+                                *state.blocking_write() = State::Closed;
+                                break;
+                            }
                         },
                         NewChat(chat) => {
                             let State::Authorized { ref mut chats, .. } = *state.blocking_write()
@@ -123,7 +202,15 @@ impl Client {
                                 continue;
                             };
 
-                            chats.insert(chat.chat.id, serde_json::to_value(chat.chat).unwrap());
+                            let id = chat.chat.id;
+                            let chat = tdlib_rs::enums::Chat::Chat(chat.chat);
+                            chats.insert(id, serde_json::to_value(chat).unwrap());
+                        }
+                        // TDLib may send this before the authorization is ready,
+                        // and only again once the folders change.
+                        ChatFolders(update) => {
+                            *folder_ids.blocking_write() =
+                                Some(update.chat_folders.iter().map(|f| f.id).collect());
                         }
                         _ => (), // ignore
                     }
@@ -133,6 +220,7 @@ impl Client {
         Ok(Client {
             config,
             state,
+            folder_ids,
             handle,
         })
     }
@@ -165,16 +253,45 @@ impl Client {
 
         for _ in 0..10 {
             match *self.state.read().await {
-                State::AwaitingPhoneNumber | State::AwaitingCode | State::Authorized { .. } => {
-                    break;
-                }
-                _ => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+                State::Init => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+                _ => break,
             }
         }
 
         Ok(self)
     }
 
+    /// Returns what the client needs to log in, or `None` while it is
+    /// still starting up.
+    pub async fn auth_step(&self) -> Option<AuthStep> {
+        Some(match *self.state.read().await {
+            State::Init => return None,
+            State::AwaitingPhoneNumber => AuthStep::PhoneNumber,
+            State::AwaitingCode => AuthStep::Code,
+            State::AwaitingPassword {
+                ref hint,
+                ref recovery_email,
+            } => AuthStep::Password {
+                hint: hint.clone(),
+                recovery_email: recovery_email.clone(),
+            },
+            State::AwaitingOtherDeviceConfirmation { ref link } => {
+                AuthStep::OtherDeviceConfirmation { link: link.clone() }
+            }
+            State::AwaitingEmailAddress => AuthStep::EmailAddress,
+            State::AwaitingEmailCode { ref email_pattern } => AuthStep::EmailCode {
+                email_pattern: email_pattern.clone(),
+            },
+            State::AwaitingRegistration {
+                ref terms_of_service,
+            } => AuthStep::Registration {
+                terms_of_service: terms_of_service.clone(),
+            },
+            State::Authorized { .. } => AuthStep::Ready,
+            State::LoggingOut | State::Closing | State::Closed => AuthStep::Closed,
+        })
+    }
+
     pub async fn is_authorised(&self) -> bool {
         matches!(*self.state.read().await, State::Authorized { .. })
     }
@@ -184,7 +301,10 @@ impl Client {
     }
 
     pub async fn is_need_password(&self, hint: &mut String) -> bool {
-        if let State::AwaitingPassword { hint: ref hint2 } = *self.state.read().await {
+        if let State::AwaitingPassword {
+            hint: ref hint2, ..
+        } = *self.state.read().await
+        {
             *hint = hint2.clone();
             return true;
         }
@@ -192,6 +312,22 @@ impl Client {
         false
     }
 
+    /// Returns the `tg://login?token=` link to confirm a QR code login with,
+    /// while one is pending. The link changes whenever its token expires.
+    pub async fn qr_code_link(&self) -> Option<String> {
+        match *self.state.read().await {
+            State::AwaitingOtherDeviceConfirmation { ref link } => Some(link.clone()),
+            _ => None,
+        }
+    }
+
+    /// Starts a QR code login, to be confirmed on an already logged-in device.
+    pub async fn request_qr_code_authentication(&self) -> Result<()> {
+        tdlib_rs::functions::request_qr_code_authentication(Vec::new(), self.handle.0)
+            .await
+            .map_err(|e| miette!("Failed to request QR code authentication: {}", e.message))
+    }
+
     pub async fn send_auth_request(&self, phone_number: &str) -> Result<()> {
         assert_eq!(*self.state.read().await, State::AwaitingPhoneNumber);
 
@@ -212,6 +348,47 @@ impl Client {
             .map_err(|e| miette!("Failed to confirm authentication code: {}", e.message))
     }
 
+    pub async fn send_email_address(&self, email_address: &str) -> Result<()> {
+        assert_eq!(*self.state.read().await, State::AwaitingEmailAddress);
+
+        tdlib_rs::functions::set_authentication_email_address(email_address.into(), self.handle.0)
+            .await
+            .map_err(|e| miette!("Failed to set email address: {}", e.message))
+    }
+
+    pub async fn send_email_code(&self, code: &str) -> Result<()> {
+        use tdlib_rs::{enums::EmailAddressAuthentication, types::EmailAddressAuthenticationCode};
+
+        assert!(matches!(
+            *self.state.read().await,
+            State::AwaitingEmailCode { .. }
+        ));
+
+        tdlib_rs::functions::check_authentication_email_code(
+            EmailAddressAuthentication::Code(EmailAddressAuthenticationCode { code: code.into() }),
+            self.handle.0,
+        )
+        .await
+        .map_err(|e| miette!("Failed to confirm email code: {}", e.message))
+    }
+
+    /// Registers a new account, accepting the terms of service.
+    pub async fn register_user(&self, first_name: &str, last_name: &str) -> Result<()> {
+        assert!(matches!(
+            *self.state.read().await,
+            State::AwaitingRegistration { .. }
+        ));
+
+        tdlib_rs::functions::register_user(
+            first_name.into(),
+            last_name.into(),
+            false,
+            self.handle.0,
+        )
+        .await
+        .map_err(|e| miette!("Failed to register: {}", e.message))
+    }
+
     pub async fn send_auth_password(&self, password: &str) -> Result<(), tdlib_rs::types::Error> {
         assert!(matches!(
             *self.state.read().await,
@@ -221,6 +398,74 @@ impl Client {
         tdlib_rs::functions::check_authentication_password(password.into(), self.handle.0).await
     }
 
+    /// Sends a code for recovering the 2FA password to the recovery email
+    /// address of the account.
+    pub async fn request_password_recovery(&self) -> Result<()> {
+        assert!(matches!(
+            *self.state.read().await,
+            State::AwaitingPassword { .. }
+        ));
+
+        tdlib_rs::functions::request_authentication_password_recovery(self.handle.0)
+            .await
+            .map_err(|e| miette!("Failed to request password recovery: {}", e.message))
+    }
+
+    /// Logs in with a password recovery code, replacing the 2FA password
+    /// with `new_password`, or turning it off if that is empty.
+    pub async fn recover_password(
+        &self,
+        recovery_code: &str,
+        new_password: &str,
+        new_hint: &str,
+    ) -> Result<()> {
+        assert!(matches!(
+            *self.state.read().await,
+            State::AwaitingPassword { .. }
+        ));
+
+        tdlib_rs::functions::recover_authentication_password(
+            recovery_code.into(),
+            new_password.into(),
+            new_hint.into(),
+            self.handle.0,
+        )
+        .await
+        .map_err(|e| miette!("Failed to recover password: {}", e.message))
+    }
+
+    /// Logs out, which also deletes the local database, and waits for the
+    /// client to close.
+    pub async fn log_out(&self) -> Result<()> {
+        tdlib_rs::functions::log_out(self.handle.0)
+            .await
+            .map_err(|e| miette!("Failed to log out: {}", e.message))?;
+
+        tokio::time::timeout(std::time::Duration::from_secs(60), async {
+            while *self.state.read().await != State::Closed {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .map_err(|_| miette!("Timed out waiting for the client to close"))
+    }
+
+    /// Returns the session of this client, as listed among the account's
+    /// active sessions.
+    pub async fn get_current_session(&self) -> Result<tdlib_rs::types::Session> {
+        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+
+        let tdlib_rs::enums::Sessions::Sessions(sessions) =
+            tdlib_rs::functions::get_active_sessions(self.handle.0)
+                .await
+                .map_err(|e| miette!("Failed to get active sessions: {}", e.message))?;
+        sessions
+            .sessions
+            .into_iter()
+            .find(|session| session.is_current)
+            .ok_or_else(|| miette!("The current session is not among the active sessions"))
+    }
+
     pub async fn get_chat_ids(&self) -> Result<BTreeSet<i64>> {
         assert!(matches!(*self.state.read().await, State::Authorized { .. }));
 
@@ -245,6 +490,71 @@ impl Client {
         Ok(chats.clone())
     }
 
+    /// Returns the chats in a chat list, in the order of the list.
+    pub async fn get_chat_list(
+        &self,
+        list: crate::ChatList,
+        limit: Option<usize>,
+    ) -> Result<Vec<Value>> {
+        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+
+        let list = match list {
+            crate::ChatList::Main => tdlib_rs::enums::ChatList::Main,
+            crate::ChatList::Archive => tdlib_rs::enums::ChatList::Archive,
+            crate::ChatList::Folder(chat_folder_id) => {
+                tdlib_rs::enums::ChatList::Folder(tdlib_rs::types::ChatListFolder {
+                    chat_folder_id,
+                })
+            }
+        };
+        self.load_chat_list(&list)
+            .await
+            .context("Failed to load chats")?;
+
+        let limit = limit.unwrap_or(i32::MAX as usize).min(i32::MAX as usize);
+        let tdlib_rs::enums::Chats::Chats(chats) =
+            tdlib_rs::functions::get_chats(Some(list), limit as i32, self.handle.0)
+                .await
+                .map_err(|e| miette!("Failed to get chats: {}", e.message))?;
+
+        let mut result = Vec::with_capacity(chats.chat_ids.len());
+        for chat_id in chats.chat_ids {
+            result.push(self.get_chat_record(chat_id).await?);
+        }
+        Ok(result)
+    }
+
+    /// Returns the user's chat folders, with their names and the rules that
+    /// decide which chats they contain.
+    pub async fn get_chat_folders(&self) -> Result<Vec<Value>> {
+        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+
+        // Don't hold the lock while waiting on TDLib, whose updates need it.
+        let folder_ids = tokio::time::timeout(std::time::Duration::from_secs(60), async {
+            loop {
+                if let Some(ref folder_ids) = *self.folder_ids.read().await {
+                    break folder_ids.clone();
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .map_err(|_| miette!("Timed out waiting for chat folders"))?;
+
+        let mut result = Vec::with_capacity(folder_ids.len());
+        for folder_id in folder_ids {
+            let folder = tdlib_rs::functions::get_chat_folder(folder_id, self.handle.0)
+                .await
+                .map_err(|e| miette!("Failed to get chat folder {folder_id}: {}", e.message))?;
+            let mut folder = serde_json::to_value(folder).into_diagnostic()?;
+            if let Some(fields) = folder.as_object_mut() {
+                fields.insert("id".into(), folder_id.into());
+            }
+            result.push(folder);
+        }
+        Ok(result)
+    }
+
     pub async fn get_chat_info(&self, chat_id: i64) -> Result<Value> {
         assert!(matches!(*self.state.read().await, State::Authorized { .. }));
 
@@ -260,11 +570,69 @@ impl Client {
             .ok_or(miette!("Unknown chat ID: {chat_id}"))
     }
 
+    /// Streams the groups and channels that the logged-in user has in common
+    /// with a user, as the same records as [`Client::get_chats`].
+    pub async fn get_groups_in_common(
+        &self,
+        user_id: i64,
+        limit: Option<usize>,
+    ) -> Result<impl futures::Stream<Item = Result<Value>>> {
+        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+
+        Ok(async_stream::try_stream! {
+            let mut offset_chat_id = 0;
+            let mut count = 0usize;
+            loop {
+                let batch = limit.map_or(100, |limit| limit.saturating_sub(count).min(100));
+                if batch == 0 {
+                    break;
+                }
+
+                let tdlib_rs::enums::Chats::Chats(chats) = tdlib_rs::functions::get_groups_in_common(
+                    user_id,
+                    offset_chat_id,
+                    batch as i32,
+                    self.handle.0,
+                )
+                .await
+                .map_err(|e| miette!("Failed to get common chats: {}", e.message))?;
+
+                let Some(&last_chat_id) = chats.chat_ids.last() else {
+                    break;
+                };
+                for chat_id in chats.chat_ids {
+                    count += 1;
+                    yield self.get_chat_record(chat_id).await?;
+                }
+                if count >= chats.total_count.max(0) as usize {
+                    break;
+                }
+                offset_chat_id = last_chat_id;
+            }
+        })
+    }
+
+    /// Returns the record of a chat, from the chat list if it is known there.
+    async fn get_chat_record(&self, chat_id: i64) -> Result<Value> {
+        if let State::Authorized { ref chats, .. } = *self.state.read().await
+            && let Some(chat) = chats.get(&chat_id)
+        {
+            return Ok(chat.clone());
+        }
+
+        tdlib_rs::functions::get_chat(chat_id, self.handle.0)
+            .await
+            .map_err(|e| miette!("Failed to get chat: {}", e.message))
+            .and_then(|chat| serde_json::to_value(chat).into_diagnostic())
+    }
+
     pub async fn get_chat_members(
         &self,
         chat_id: i64,
+        filter: MemberFilter,
+        exhaustive: bool,
         limit: Option<usize>,
-    ) -> Result<impl futures::Stream<Item = Result<Value>>> {
+    ) -> Result<BoxStream<'_, Result<Value>>> {
         assert!(matches!(*self.state.read().await, State::Authorized { .. }));
 
         self.load_chats().await.context("Failed to load chats")?;
@@ -288,28 +656,39 @@ impl Client {
         let stream = async_stream::try_stream! {
             match chat.r#type {
                 BasicGroup(ChatTypeBasicGroup { basic_group_id }) => {
-                    let members = self.get_basicgroup_members(basic_group_id).await?;
-                    let members = if let Some(limit) = limit
-                        && let Some((left, _right)) = members.split_at_checked(limit)
-                    {
-                        left.into()
-                    } else {
-                        members
-                    };
+                    // Basic groups have no server-side filters, so filter locally.
+                    let mut count = 0usize;
+                    for member in self.basicgroup_members(basic_group_id).await? {
+                        if limit.is_some_and(|limit| count >= limit) {
+                            break;
+                        }
+                        if !self.member_matches(&member, &filter).await? {
+                            continue;
+                        }
+                        let member = tdlib_rs::enums::ChatMember::ChatMember(member);
+                        count += 1;
+                        yield with_chat_id(serde_json::to_value(member).into_diagnostic()?, chat_id);
+                    }
+                }
+                Supergroup(ChatTypeSupergroup { supergroup_id, .. }) if exhaustive => {
+                    if filter != MemberFilter::default() {
+                        Err(miette!("Exhaustive member listings can't be filtered"))?;
+                    }
+                    let members = self.get_supergroup_members_exhaustive(supergroup_id, limit);
 
-                    for member in members {
-                        yield member;
+                    for await member in members {
+                        yield with_chat_id(member?, chat_id);
                     }
                 }
                 Supergroup(ChatTypeSupergroup { supergroup_id, .. }) => {
-                    let members = self.get_supergroup_members(supergroup_id, limit).await?;
+                    let members = self.get_supergroup_members(supergroup_id, filter, limit).await?;
 
                     for await member in members {
-                            yield member?;
+                        yield with_chat_id(member?, chat_id);
                     }
                 }
                 Private(ChatTypePrivate { user_id }) | Secret(ChatTypeSecret { user_id, .. }) => {
-                    let member = tdlib_rs::functions::get_chat_member(
+                    let tdlib_rs::enums::ChatMember::ChatMember(member) = tdlib_rs::functions::get_chat_member(
                         chat_id,
                         tdlib_rs::enums::MessageSender::User(tdlib_rs::types::MessageSenderUser { user_id }),
                         self.handle.0,
@@ -317,30 +696,108 @@ impl Client {
                     .await
                     .map_err(|e| miette!("Failed to fetch chat member: {}", e.message))?;
 
-                    yield serde_json::to_value(member).into_diagnostic()?;
+                    if self.member_matches(&member, &filter).await? {
+                        let member = tdlib_rs::enums::ChatMember::ChatMember(member);
+                        yield with_chat_id(serde_json::to_value(member).into_diagnostic()?, chat_id);
+                    }
                 }
             }
         };
 
-        Ok(stream)
+        Ok(stream.boxed())
+    }
+
+    /// Returns the member count of a group or channel, or `None` for
+    /// private chats.
+    pub async fn get_member_count(&self, chat_id: i64) -> Result<Option<i32>> {
+        use tdlib_rs::enums::{BasicGroup, ChatType, SupergroupFullInfo};
+
+        let tdlib_rs::enums::Chat::Chat(chat) =
+            tdlib_rs::functions::get_chat(chat_id, self.handle.0)
+                .await
+                .map_err(|e| miette!("Failed to get chat: {}", e.message))?;
+
+        match chat.r#type {
+            ChatType::BasicGroup(group) => {
+                let BasicGroup::BasicGroup(group) =
+                    tdlib_rs::functions::get_basic_group(group.basic_group_id, self.handle.0)
+                        .await
+                        .map_err(|e| miette!("Failed to get basic group: {}", e.message))?;
+                Ok(Some(group.member_count))
+            }
+            ChatType::Supergroup(group) => {
+                let SupergroupFullInfo::SupergroupFullInfo(info) =
+                    tdlib_rs::functions::get_supergroup_full_info(
+                        group.supergroup_id,
+                        self.handle.0,
+                    )
+                    .await
+                    .map_err(|e| miette!("Failed to get supergroup info: {}", e.message))?;
+                Ok(Some(info.member_count))
+            }
+            ChatType::Private(_) | ChatType::Secret(_) => Ok(None),
+        }
     }
 
     pub async fn get_basicgroup_members(&self, basicgroup_id: i64) -> Result<Vec<Value>> {
-        tdlib_rs::functions::get_basic_group_full_info(basicgroup_id, self.handle.0)
-            .await
-            .map_err(|e| miette!(e.message))
+        self.basicgroup_members(basicgroup_id)
+            .await?
             .into_iter()
-            .flat_map(|tdlib_rs::enums::BasicGroupFullInfo::BasicGroupFullInfo(info)| info.members)
+            .map(tdlib_rs::enums::ChatMember::ChatMember)
             .map(|member| serde_json::to_value(member).into_diagnostic())
             .collect()
     }
 
+    async fn basicgroup_members(
+        &self,
+        basicgroup_id: i64,
+    ) -> Result<Vec<tdlib_rs::types::ChatMember>> {
+        tdlib_rs::functions::get_basic_group_full_info(basicgroup_id, self.handle.0)
+            .await
+            .map(|tdlib_rs::enums::BasicGroupFullInfo::BasicGroupFullInfo(info)| info.members)
+            .map_err(|e| miette!(e.message))
+    }
+
     pub async fn get_supergroup_members(
         &self,
         supergroup_id: i64,
+        filter: MemberFilter,
         limit: Option<usize>,
     ) -> Result<impl futures::Stream<Item = Result<Value>>> {
+        use tdlib_rs::{
+            enums::SupergroupMembersFilter as Filter,
+            types::{
+                SupergroupMembersFilterBanned, SupergroupMembersFilterContacts,
+                SupergroupMembersFilterRestricted, SupergroupMembersFilterSearch,
+            },
+        };
+
+        let query = filter.query.clone().unwrap_or_default();
+        // Only some of the server-side filters support a query.
+        let (server_filter, local_query) = match filter.role {
+            MemberRole::Any if query.is_empty() => (Filter::Recent, false),
+            MemberRole::Any => (
+                Filter::Search(SupergroupMembersFilterSearch { query }),
+                false,
+            ),
+            MemberRole::Administrators => (Filter::Administrators, !query.is_empty()),
+            MemberRole::Bots => (Filter::Bots, !query.is_empty()),
+            MemberRole::Banned => (
+                Filter::Banned(SupergroupMembersFilterBanned { query }),
+                false,
+            ),
+            MemberRole::Restricted => (
+                Filter::Restricted(SupergroupMembersFilterRestricted { query }),
+                false,
+            ),
+            MemberRole::Contacts => (
+                Filter::Contacts(SupergroupMembersFilterContacts { query }),
+                false,
+            ),
+        };
+
         let stream = async_stream::try_stream! {
+            let mut offset = 0usize;
             let mut count = 0usize;
             loop {
                 let limit = if let Some(max) = limit {
@@ -358,8 +815,8 @@ impl Client {
                     std::time::Duration::from_secs(60),
                     tdlib_rs::functions::get_supergroup_members(
                         supergroup_id,
-                        None,
-                        count as i32,
+                        Some(server_filter.clone()),
+                        offset as i32,
                         limit as i32,
                         self.handle.0,
                     ),
@@ -373,6 +830,11 @@ impl Client {
                             break;
                         }
                         for member in members {
+                            offset += 1;
+                            if local_query && !self.member_matches(&member, &filter).await? {
+                                continue;
+                            }
+                            let member = tdlib_rs::enums::ChatMember::ChatMember(member);
                             let member = serde_json::to_value(member).into_diagnostic()?;
                             count += 1;
                             yield member;
@@ -388,85 +850,810 @@ impl Client {
         Ok(stream)
     }
 
-    pub async fn get_user(&self, user_id: i64) -> Result<Value> {
-        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+    /// Lists supergroup members beyond the server's cap on member lists.
+    ///
+    /// The server returns at most a few thousand members for any one query,
+    /// so when the plain listing comes up short of its total count, members
+    /// are searched for by name prefix, extending each prefix that is itself
+    /// capped. Members are de-duplicated by sender ID. Members whose names
+    /// share no prefix with [`MEMBER_SEARCH_ALPHABETS`] may still be missed.
+    pub fn get_supergroup_members_exhaustive(
+        &self,
+        supergroup_id: i64,
+        limit: Option<usize>,
+    ) -> impl futures::Stream<Item = Result<Value>> {
+        use tdlib_rs::{
+            enums::{MessageSender, SupergroupMembersFilter as Filter},
+            types::SupergroupMembersFilterSearch,
+        };
 
-        tdlib_rs::functions::get_user(user_id, self.handle.0)
-            .await
-            .map_err(|e| miette!("Failed to get user: {}", e.message))
-            .and_then(|user| serde_json::to_value(user).into_diagnostic())
-    }
+        async_stream::try_stream! {
+            let mut seen = BTreeSet::new();
+            let mut queries = std::collections::VecDeque::from([String::new()]);
 
-    async fn load_chats(&self) -> Result<()> {
-        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+            while let Some(query) = queries.pop_front() {
+                let filter = if query.is_empty() {
+                    Filter::Recent
+                } else {
+                    Filter::Search(SupergroupMembersFilterSearch { query: query.clone() })
+                };
+                let (members, total_count) = self.supergroup_members_page_all(supergroup_id, filter).await?;
 
-        let chat_lists = std::vec![
-            tdlib_rs::enums::ChatList::Main,
-            tdlib_rs::enums::ChatList::Archive,
-        ];
+                tracing::debug!(%query, found = members.len(), total_count, "searched members");
 
-        for list in chat_lists {
-            loop {
-                match tdlib_rs::functions::load_chats(Some(list.clone()), 100, self.handle.0).await
-                {
-                    Ok(_) => (),
-                    Err(err) if err.code == 404 => break,
-                    Err(err) => bail!(err.message),
+                if members.len() < total_count as usize && query.chars().count() < MEMBER_SEARCH_MAX_PREFIX {
+                    queries.extend(member_search_prefixes(&query));
+                }
+
+                for member in members {
+                    if limit.is_some_and(|limit| seen.len() >= limit) {
+                        return;
+                    }
+                    let key = match member.member_id {
+                        MessageSender::User(ref user) => user.user_id,
+                        MessageSender::Chat(ref chat) => chat.chat_id,
+                    };
+                    if !seen.insert(key) {
+                        continue;
+                    }
+                    let member = tdlib_rs::enums::ChatMember::ChatMember(member);
+                    yield serde_json::to_value(member).into_diagnostic()?;
                 }
             }
         }
-
-        Ok(())
     }
 
-    pub async fn get_chat_history(
+    /// Fetches every member the server returns for a filter, along with the
+    /// total count the server reports for it. Requests that are rate-limited
+    /// are retried after the delay the server asks for.
+    async fn supergroup_members_page_all(
         &self,
-        chat_id: i64,
-        from_msg_id: Option<i64>,
-        limit: Option<usize>,
-    ) -> Result<impl futures::Stream<Item = Result<Message>>> {
-        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
-
-        self.load_chats().await.context("Failed to load chats")?;
+        supergroup_id: i64,
+        filter: tdlib_rs::enums::SupergroupMembersFilter,
+    ) -> Result<(Vec<tdlib_rs::types::ChatMember>, i32)> {
+        let mut result = Vec::new();
+        let mut total_count = 0;
+        loop {
+            let res = tokio::time::timeout(
+                std::time::Duration::from_secs(60),
+                tdlib_rs::functions::get_supergroup_members(
+                    supergroup_id,
+                    Some(filter.clone()),
+                    result.len() as i32,
+                    200,
+                    self.handle.0,
+                ),
+            )
+            .await
+            .map_err(|_| miette!("Request timed out"))?;
 
-        if let State::Authorized { ref chats, .. } = *self.state.read().await {
-            if !chats.contains_key(&chat_id) {
-                bail!("Chat ID {} not found", chat_id);
+            match res {
+                Ok(tdlib_rs::enums::ChatMembers::ChatMembers(members)) => {
+                    total_count = members.total_count;
+                    if members.members.is_empty() {
+                        break;
+                    }
+                    result.extend(members.members);
+                }
+                Err(err) => match flood_wait(&err) {
+                    Some(delay) => {
+                        tracing::warn!(?delay, "rate-limited while searching members, waiting");
+                        tokio::time::sleep(delay).await;
+                    }
+                    None if err.code == 400 => break,
+                    None => bail!("Failed to get chat members: {}", err.message),
+                },
             }
-        } else {
-            bail!("Unexpectedly got unauthorized");
+        }
+        Ok((result, total_count))
+    }
+
+    /// Checks a chat member against a filter locally.
+    async fn member_matches(
+        &self,
+        member: &tdlib_rs::types::ChatMember,
+        filter: &MemberFilter,
+    ) -> Result<bool> {
+        use tdlib_rs::enums::{ChatMemberStatus, MessageSender, UserType};
+
+        let role_matches = match filter.role {
+            MemberRole::Any | MemberRole::Bots | MemberRole::Contacts => true,
+            MemberRole::Administrators => matches!(
+                member.status,
+                ChatMemberStatus::Creator(_) | ChatMemberStatus::Administrator(_)
+            ),
+            MemberRole::Banned => matches!(member.status, ChatMemberStatus::Banned(_)),
+            MemberRole::Restricted => matches!(member.status, ChatMemberStatus::Restricted(_)),
         };
+        let needs_user = matches!(filter.role, MemberRole::Bots | MemberRole::Contacts)
+            || filter.query.is_some();
+        if !role_matches || !needs_user {
+            return Ok(role_matches);
+        }
 
-        let stream = async_stream::try_stream! {
-            let mut from_msg_id = from_msg_id;
-            let mut count = 0usize;
+        let MessageSender::User(ref sender) = member.member_id else {
+            return Ok(false);
+        };
+        let tdlib_rs::enums::User::User(user) =
+            tdlib_rs::functions::get_user(sender.user_id, self.handle.0)
+                .await
+                .map_err(|e| miette!("Failed to get user: {}", e.message))?;
 
-            loop {
-                let limit = if let Some(limit) = limit {
-                    limit.saturating_sub(count).min(100)
-                } else {
-                    100
-                };
-                if limit == 0 {
-                    break;
-                }
+        let role_matches = match filter.role {
+            MemberRole::Bots => matches!(user.r#type, UserType::Bot(_)),
+            MemberRole::Contacts => user.is_contact,
+            _ => true,
+        };
+        let query_matches = filter.query.as_deref().is_none_or(|query| {
+            let query = query.to_lowercase();
+            let usernames = user.usernames.iter().flat_map(|u| &u.active_usernames);
+            [&user.first_name, &user.last_name]
+                .into_iter()
+                .chain(usernames)
+                .any(|name| name.to_lowercase().contains(&query))
+        });
 
-                tracing::debug!(count = count, limit, "fetching messages...");
+        Ok(role_matches && query_matches)
+    }
 
-                let tdlib_rs::enums::Messages::Messages(batch) = tokio::time::timeout(
+    pub async fn get_file(&self, file_id: i32) -> Result<tdlib_rs::types::File> {
+        tdlib_rs::functions::get_file(file_id, self.handle.0)
+            .await
+            .map(|tdlib_rs::enums::File::File(file)| file)
+            .map_err(|e| miette!("Failed to get file {file_id}: {}", e.message))
+    }
+
+    /// Downloads a file, waiting for the download to complete. Returns `None`
+    /// without downloading if `limits` exclude the file.
+    pub async fn download_file(
+        &self,
+        file: &tdlib_rs::types::File,
+        mime_type: Option<&str>,
+        limits: &MediaLimits,
+    ) -> Result<Option<tdlib_rs::types::File>> {
+        if !limits.allows(media::file_size(file), mime_type) {
+            return Ok(None);
+        }
+        if file.local.is_downloading_completed {
+            return Ok(Some(file.clone()));
+        }
+
+        tdlib_rs::functions::download_file(file.id, 1, 0, 0, true, self.handle.0)
+            .await
+            .map(|tdlib_rs::enums::File::File(file)| Some(file))
+            .map_err(|e| miette!("Failed to download file {}: {}", file.id, e.message))
+    }
+
+    /// Downloads the file a message carries into `store`, returning its path
+    /// there, or `None` if the message has no file or `limits` exclude it.
+    pub async fn download_message_media(
+        &self,
+        msg: &Message,
+        store: &MediaStore,
+        limits: &MediaLimits,
+    ) -> Result<Option<PathBuf>> {
+        let Some((file, mime_type)) = media::message_file(&msg.content) else {
+            return Ok(None);
+        };
+        let Some(file) = self.download_file(file, mime_type, limits).await? else {
+            tracing::debug!(file_id = file.id, "Skipped media download");
+            return Ok(None);
+        };
+        store
+            .store(std::path::Path::new(&file.local.path))
+            .map(Some)
+    }
+
+    /// Serializes a message, first downloading its media into `media` if
+    /// given. A failed download is logged, and the message is still returned.
+    pub async fn message_record(
+        &self,
+        msg: Message,
+        media: Option<&MediaStore>,
+        limits: &MediaLimits,
+    ) -> Result<Value> {
+        let local_path = match media {
+            Some(store) => self
+                .download_message_media(&msg, store, limits)
+                .await
+                .unwrap_or_else(|err| {
+                    tracing::warn!(%err, message_id = msg.id, "Failed to download media");
+                    None
+                }),
+            None => None,
+        };
+
+        let mut record =
+            serde_json::to_value(tdlib_rs::enums::Message::Message(msg)).into_diagnostic()?;
+        if let Some(path) = local_path {
+            record["local_path"] = path.display().to_string().into();
+        }
+        Ok(record)
+    }
+
+    pub async fn get_user(&self, user_id: i64) -> Result<Value> {
+        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+
+        tdlib_rs::functions::get_user(user_id, self.handle.0)
+            .await
+            .map_err(|e| miette!("Failed to get user: {}", e.message))
+            .and_then(|user| serde_json::to_value(user).into_diagnostic())
+    }
+
+    /// Returns the logged-in user.
+    pub async fn get_me(&self) -> Result<Value> {
+        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+
+        tdlib_rs::functions::get_me(self.handle.0)
+            .await
+            .map_err(|e| miette!("Failed to get the current user: {}", e.message))
+            .and_then(|user| serde_json::to_value(user).into_diagnostic())
+    }
+
+    /// Streams the users in the address book of the logged-in user.
+    pub async fn get_contacts(
+        &self,
+        limit: Option<usize>,
+    ) -> Result<impl futures::Stream<Item = Result<Value>>> {
+        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+
+        let tdlib_rs::enums::Users::Users(users) = tdlib_rs::functions::get_contacts(self.handle.0)
+            .await
+            .map_err(|e| miette!("Failed to get contacts: {}", e.message))?;
+
+        let user_ids = users.user_ids.into_iter().take(limit.unwrap_or(usize::MAX));
+
+        Ok(async_stream::try_stream! {
+            for user_id in user_ids {
+                yield self.get_user(user_id).await?;
+            }
+        })
+    }
+
+    /// Streams the profile photo history of a user, current photo first.
+    pub fn get_user_profile_photos(
+        &self,
+        user_id: i64,
+        limit: Option<usize>,
+    ) -> impl futures::Stream<Item = Result<Value>> {
+        async_stream::try_stream! {
+            let mut count = 0usize;
+            loop {
+                let batch = limit.map_or(100, |limit| limit.saturating_sub(count).min(100));
+                if batch == 0 {
+                    break;
+                }
+
+                let tdlib_rs::enums::ChatPhotos::ChatPhotos(photos) =
+                    tdlib_rs::functions::get_user_profile_photos(
+                        user_id,
+                        count as i32,
+                        batch as i32,
+                        self.handle.0,
+                    )
+                    .await
+                    .map_err(|e| miette!("Failed to get profile photos: {}", e.message))?;
+
+                if photos.photos.is_empty() {
+                    break;
+                }
+                for photo in photos.photos {
+                    let photo = tdlib_rs::enums::ChatPhoto::ChatPhoto(photo);
+                    let photo = serde_json::to_value(photo).into_diagnostic()?;
+                    count += 1;
+                    yield with_user_id(photo, user_id);
+                }
+                if count >= photos.total_count.max(0) as usize {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Downloads the avatar of a chat or user record, or the photo of a
+    /// `chatPhoto` record, into `store` and adds its path there to the record
+    /// as `avatar_path`. Records without a photo are left as they are.
+    pub async fn add_avatar(
+        &self,
+        record: &mut Value,
+        size: AvatarSize,
+        store: &MediaStore,
+    ) -> Result<()> {
+        let file = match record["@type"].as_str() {
+            Some("chat") => &record["photo"][size.field()],
+            Some("user") => &record["profile_photo"][size.field()],
+            Some("chatPhoto") => match size {
+                AvatarSize::Small => &record["sizes"][0]["photo"],
+                AvatarSize::Big => record["sizes"]
+                    .as_array()
+                    .and_then(|sizes| sizes.last())
+                    .map_or(&Value::Null, |size| &size["photo"]),
+            },
+            _ => &Value::Null,
+        };
+        if file.is_null() {
+            return Ok(());
+        }
+        let file: tdlib_rs::types::File = serde_json::from_value(file.clone()).into_diagnostic()?;
+
+        let Some(file) = self
+            .download_file(&file, None, &MediaLimits::default())
+            .await?
+        else {
+            return Ok(());
+        };
+        let path = store.store(std::path::Path::new(&file.local.path))?;
+        record["avatar_path"] = path.display().to_string().into();
+        Ok(())
+    }
+
+    /// Returns `record` with its avatar added as by [`Self::add_avatar`], if
+    /// `avatars` is given. Failed downloads are logged and leave the record
+    /// without an `avatar_path`.
+    pub async fn with_avatar(
+        &self,
+        mut record: Value,
+        avatars: Option<&(AvatarSize, MediaStore)>,
+    ) -> Value {
+        if let Some((size, store)) = avatars
+            && let Err(err) = self.add_avatar(&mut record, *size, store).await
+        {
+            tracing::warn!(%err, "Failed to download avatar");
+        }
+        record
+    }
+
+    /// Looks up a public chat by its username, e.g. `durov`.
+    pub async fn search_public_chat(&self, username: &str) -> Result<i64> {
+        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+
+        tdlib_rs::functions::search_public_chat(username.into(), self.handle.0)
+            .await
+            .map(|tdlib_rs::enums::Chat::Chat(chat)| chat.id)
+            .map_err(|e| miette!("Failed to resolve username `{username}`: {}", e.message))
+    }
+
+    pub async fn get_message(&self, chat_id: i64, message_id: i64) -> Result<Message> {
+        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+
+        self.load_chats().await.context("Failed to load chats")?;
+
+        tdlib_rs::functions::get_message(chat_id, message_id, self.handle.0)
+            .await
+            .map(|tdlib_rs::enums::Message::Message(msg)| msg)
+            .map_err(|e| miette!("Failed to get message: {}", e.message))
+    }
+
+    /// Returns the message that the given message replies to.
+    pub async fn get_replied_message(&self, chat_id: i64, message_id: i64) -> Result<Message> {
+        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+
+        tdlib_rs::functions::get_replied_message(chat_id, message_id, self.handle.0)
+            .await
+            .map(|tdlib_rs::enums::Message::Message(msg)| msg)
+            .map_err(|e| miette!("Failed to get replied message: {}", e.message))
+    }
+
+    /// Returns the `https://t.me/` link to a message. Only messages in
+    /// supergroups and channels have links.
+    pub async fn get_message_link(&self, chat_id: i64, message_id: i64) -> Result<String> {
+        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+
+        tdlib_rs::functions::get_message_link(chat_id, message_id, 0, false, false, self.handle.0)
+            .await
+            .map(|tdlib_rs::enums::MessageLink::MessageLink(link)| link.link)
+            .map_err(|e| miette!("Failed to get message link: {}", e.message))
+    }
+
+    /// Resolves targets that refer to chats by username into targets that
+    /// refer to them by ID. Other targets are returned unchanged.
+    pub async fn resolve(&self, target: FetchTarget) -> Result<FetchTarget> {
+        Ok(match target {
+            FetchTarget::PublicChat { username } => FetchTarget::Chat {
+                chat_id: self.search_public_chat(&username).await?,
+            },
+            FetchTarget::PublicChatMessage {
+                username,
+                message_id,
+            } => FetchTarget::ChatMessage {
+                chat_id: self.search_public_chat(&username).await?,
+                message_id,
+            },
+            target => target,
+        })
+    }
+
+    async fn load_chats(&self) -> Result<()> {
+        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+
+        let chat_lists = std::vec![
+            tdlib_rs::enums::ChatList::Main,
+            tdlib_rs::enums::ChatList::Archive,
+        ];
+
+        for list in chat_lists {
+            self.load_chat_list(&list).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn load_chat_list(&self, list: &tdlib_rs::enums::ChatList) -> Result<()> {
+        loop {
+            match tdlib_rs::functions::load_chats(Some(list.clone()), 100, self.handle.0).await {
+                Ok(_) => (),
+                Err(err) if err.code == 404 => break,
+                Err(err) => bail!(err.message),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the last message sent in a chat no later than `date`, if any.
+    pub async fn get_chat_message_by_date(
+        &self,
+        chat_id: i64,
+        date: i64,
+    ) -> Result<Option<Message>> {
+        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+
+        let date = date.clamp(0, i32::MAX.into()) as i32;
+        match tdlib_rs::functions::get_chat_message_by_date(chat_id, date, self.handle.0).await {
+            Ok(tdlib_rs::enums::Message::Message(msg)) => Ok(Some(msg)),
+            Err(err) if err.code == 404 => Ok(None),
+            Err(err) => Err(miette!("Failed to get message by date: {}", err.message)),
+        }
+    }
+
+    /// Streams the messages of a chat, newest first unless `order` is
+    /// [`MessageOrder::Asc`]. Messages outside of `range` are skipped.
+    pub async fn get_chat_history(
+        &self,
+        chat_id: i64,
+        range: MessageRange,
+        order: MessageOrder,
+        limit: Option<usize>,
+    ) -> Result<BoxStream<'_, Result<Message>>> {
+        self.ensure_chat(chat_id).await?;
+
+        let handle = self.handle.0;
+        let fetch = move |from_msg_id, offset, limit| {
+            tdlib_rs::functions::get_chat_history(
+                chat_id,
+                from_msg_id,
+                offset,
+                limit,
+                false,
+                handle,
+            )
+        };
+
+        Ok(match order {
+            MessageOrder::Desc => self.history_desc(chat_id, range, limit, fetch).boxed(),
+            MessageOrder::Asc => self.history_asc(chat_id, range, limit, fetch).boxed(),
+        })
+    }
+
+    /// Streams the messages of a message thread, such as a forum topic,
+    /// in the same way as [`Client::get_chat_history`].
+    pub async fn get_message_thread_history(
+        &self,
+        chat_id: i64,
+        message_thread_id: i64,
+        range: MessageRange,
+        order: MessageOrder,
+        limit: Option<usize>,
+    ) -> Result<BoxStream<'_, Result<Message>>> {
+        self.ensure_chat(chat_id).await?;
+
+        let handle = self.handle.0;
+        let fetch = move |from_msg_id, offset, limit| {
+            tdlib_rs::functions::get_message_thread_history(
+                chat_id,
+                message_thread_id,
+                from_msg_id,
+                offset,
+                limit,
+                handle,
+            )
+        };
+
+        Ok(match order {
+            MessageOrder::Desc => self.history_desc(chat_id, range, limit, fetch).boxed(),
+            MessageOrder::Asc => self.history_asc(chat_id, range, limit, fetch).boxed(),
+        })
+    }
+
+    /// Streams the replies to a message, such as the comments on a channel
+    /// post, which live in the thread of the channel's discussion group.
+    pub async fn get_message_replies(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+        range: MessageRange,
+        order: MessageOrder,
+        limit: Option<usize>,
+    ) -> Result<BoxStream<'_, Result<Message>>> {
+        self.ensure_chat(chat_id).await?;
+
+        let tdlib_rs::enums::MessageThreadInfo::MessageThreadInfo(thread) =
+            tdlib_rs::functions::get_message_thread(chat_id, message_id, self.handle.0)
+                .await
+                .map_err(|e| miette!("Failed to get message thread: {}", e.message))?;
+
+        tracing::debug!(
+            chat_id = thread.chat_id,
+            message_thread_id = thread.message_thread_id,
+            "resolved message thread"
+        );
+
+        self.get_message_thread_history(
+            thread.chat_id,
+            thread.message_thread_id,
+            range,
+            order,
+            limit,
+        )
+        .await
+    }
+
+    pub async fn get_forum_topics(
+        &self,
+        chat_id: i64,
+        limit: Option<usize>,
+    ) -> Result<impl futures::Stream<Item = Result<Value>>> {
+        self.ensure_chat(chat_id).await?;
+
+        let stream = async_stream::try_stream! {
+            let mut offset_date = 0;
+            let mut offset_message_id = 0;
+            let mut offset_message_thread_id = 0;
+            let mut count = 0usize;
+
+            loop {
+                let limit = if let Some(limit) = limit {
+                    limit.saturating_sub(count).min(100)
+                } else {
+                    100
+                };
+                if limit == 0 {
+                    break;
+                }
+
+                tracing::debug!(count = count, limit, "fetching topics...");
+
+                let tdlib_rs::enums::ForumTopics::ForumTopics(batch) = tokio::time::timeout(
                     std::time::Duration::from_secs(60),
-                    tdlib_rs::functions::get_chat_history(
+                    tdlib_rs::functions::get_forum_topics(
                         chat_id,
-                        from_msg_id.unwrap_or(0),
-                        0,
+                        String::new(),
+                        offset_date,
+                        offset_message_id,
+                        offset_message_thread_id,
                         limit as i32,
-                        false,
                         self.handle.0,
                     ),
                 )
                 .await
                 .map_err(|_| miette!("Request timed out"))?
-                .map_err(|e| miette!("Failed to get chat history: {}", e.message))?;
+                .map_err(|e| miette!("Failed to get forum topics: {}", e.message))?;
+
+                if batch.topics.is_empty() {
+                    break;
+                }
+
+                for topic in batch.topics {
+                    let topic = tdlib_rs::enums::ForumTopic::ForumTopic(topic);
+                    let topic = serde_json::to_value(topic).into_diagnostic()?;
+                    count += 1;
+                    yield with_chat_id(topic, chat_id);
+                }
+
+                if batch.next_offset_message_thread_id == 0 {
+                    break;
+                }
+                offset_date = batch.next_offset_date;
+                offset_message_id = batch.next_offset_message_id;
+                offset_message_thread_id = batch.next_offset_message_thread_id;
+            }
+        };
+
+        Ok(stream)
+    }
+
+    /// Streams the messages matching a search across all chats, newest first.
+    pub async fn search_messages(
+        &self,
+        search: MessageSearch,
+        limit: Option<usize>,
+    ) -> Result<BoxStream<'_, Result<Message>>> {
+        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+
+        Ok(async_stream::try_stream! {
+            let mut offset = String::new();
+            let mut count = 0usize;
+            loop {
+                let limit = if let Some(limit) = limit {
+                    limit.saturating_sub(count).min(100)
+                } else {
+                    100
+                };
+                if limit == 0 {
+                    break;
+                }
+
+                tracing::debug!(count = count, limit, "searching messages...");
+
+                let tdlib_rs::enums::FoundMessages::FoundMessages(found) = tokio::time::timeout(
+                    std::time::Duration::from_secs(60),
+                    tdlib_rs::functions::search_messages(
+                        None,
+                        search.query.clone(),
+                        core::mem::take(&mut offset),
+                        limit as i32,
+                        search.filter.map(search_filter),
+                        0,
+                        0,
+                        self.handle.0,
+                    ),
+                )
+                .await
+                .map_err(|_| miette!("Request timed out"))?
+                .map_err(|e| miette!("Failed to search messages: {}", e.message))?;
+
+                if found.messages.is_empty() {
+                    break;
+                }
+                for msg in found.messages {
+                    count += 1;
+                    yield msg;
+                }
+                if found.next_offset.is_empty() {
+                    break;
+                }
+                offset = found.next_offset;
+            }
+        }
+        .boxed())
+    }
+
+    /// Streams the messages of a chat matching a search, newest first.
+    pub async fn search_chat_messages(
+        &self,
+        chat_id: i64,
+        search: MessageSearch,
+        limit: Option<usize>,
+    ) -> Result<BoxStream<'_, Result<Message>>> {
+        use tdlib_rs::{
+            enums::MessageSender,
+            types::{MessageSenderChat, MessageSenderUser},
+        };
+
+        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+
+        self.ensure_chat(chat_id).await?;
+
+        let sender = search.sender_id.map(|id| match id {
+            chat_id if chat_id < 0 => MessageSender::Chat(MessageSenderChat { chat_id }),
+            user_id => MessageSender::User(MessageSenderUser { user_id }),
+        });
+
+        Ok(async_stream::try_stream! {
+            let mut from_message_id = 0;
+            let mut count = 0usize;
+            loop {
+                let limit = if let Some(limit) = limit {
+                    limit.saturating_sub(count).min(100)
+                } else {
+                    100
+                };
+                if limit == 0 {
+                    break;
+                }
+
+                tracing::debug!(count = count, limit, "searching chat messages...");
+
+                let tdlib_rs::enums::FoundChatMessages::FoundChatMessages(found) =
+                    tokio::time::timeout(
+                        std::time::Duration::from_secs(60),
+                        tdlib_rs::functions::search_chat_messages(
+                            chat_id,
+                            search.query.clone(),
+                            sender.clone(),
+                            from_message_id,
+                            0,
+                            limit as i32,
+                            search.filter.map(search_filter),
+                            0,
+                            0,
+                            self.handle.0,
+                        ),
+                    )
+                    .await
+                    .map_err(|_| miette!("Request timed out"))?
+                    .map_err(|e| miette!("Failed to search chat messages: {}", e.message))?;
+
+                if found.messages.is_empty() {
+                    break;
+                }
+                for msg in found.messages {
+                    count += 1;
+                    yield msg;
+                }
+                if found.next_from_message_id == 0 {
+                    break;
+                }
+                from_message_id = found.next_from_message_id;
+            }
+        }
+        .boxed())
+    }
+
+    async fn ensure_chat(&self, chat_id: i64) -> Result<()> {
+        assert!(matches!(*self.state.read().await, State::Authorized { .. }));
+
+        self.load_chats().await.context("Failed to load chats")?;
+
+        if let State::Authorized { ref chats, .. } = *self.state.read().await {
+            if !chats.contains_key(&chat_id) {
+                bail!("Chat ID {} not found", chat_id);
+            }
+        } else {
+            bail!("Unexpectedly got unauthorized");
+        };
+
+        Ok(())
+    }
+
+    /// Pages through a message history from the newest to the oldest message.
+    ///
+    /// When `range.until` is set and `range.from_id` isn't, the listing
+    /// starts from the last message before `until`, and it ends at the first
+    /// message before `range.since`.
+    fn history_desc<F, Fut>(
+        &self,
+        chat_id: i64,
+        range: MessageRange,
+        limit: Option<usize>,
+        fetch: F,
+    ) -> impl futures::Stream<Item = Result<Message>>
+    where
+        F: Fn(i64, i32, i32) -> Fut,
+        Fut: Future<Output = Result<tdlib_rs::enums::Messages, tdlib_rs::types::Error>>,
+    {
+        async_stream::try_stream! {
+            let mut from_msg_id = range.from_id;
+            let mut count = 0usize;
+
+            if from_msg_id.is_none()
+                && let Some(until) = range.until
+            {
+                match self.get_chat_message_by_date(chat_id, until).await? {
+                    // Start just above the message so that it is included too.
+                    Some(msg) => from_msg_id = Some(msg.id + 1),
+                    None => return,
+                }
+            }
+
+            'pages: loop {
+                let limit = if let Some(limit) = limit {
+                    limit.saturating_sub(count).min(100)
+                } else {
+                    100
+                };
+                if limit == 0 {
+                    break;
+                }
+
+                tracing::debug!(count = count, limit, "fetching messages...");
+
+                let tdlib_rs::enums::Messages::Messages(batch) = tokio::time::timeout(
+                    std::time::Duration::from_secs(60),
+                    fetch(from_msg_id.unwrap_or(0), 0, limit as i32),
+                )
+                .await
+                .map_err(|_| miette!("Request timed out"))?
+                .map_err(|e| miette!("Failed to get message history: {}", e.message))?;
 
                 let msgs: Vec<Message> = batch.messages.into_iter().flatten().collect();
 
@@ -474,22 +1661,214 @@ impl Client {
                     break;
                 }
 
+                let prev_msg_id = from_msg_id;
                 for msg in msgs {
                     from_msg_id = match from_msg_id {
                         Some(old_id) if old_id < msg.id => old_id,
                         Some(_) => msg.id,
                         None => msg.id,
                     }.into();
+                    if range.since.is_some_and(|since| i64::from(msg.date) < since) {
+                        break 'pages;
+                    }
+                    if !range.contains(msg.date.into()) {
+                        continue;
+                    }
                     count += 1;
                     yield msg;
                 }
+                if from_msg_id == prev_msg_id {
+                    break; // no progress
+                }
             }
-        };
+        }
+    }
 
-        Ok(stream)
+    /// Pages through a message history from the oldest to the newest message.
+    ///
+    /// The listing starts from `range.from_id`, from the last message before
+    /// `range.since`, or from the first message of the chat. Only one page
+    /// of messages is held in memory at a time.
+    ///
+    /// TDLib may return fewer messages than requested, even none past the
+    /// one a page starts from, before the end of the history. So an empty
+    /// page only ends the listing once the chat's last message has been
+    /// reached, or after [`EMPTY_PAGE_RETRIES`] retries. Listings of message
+    /// threads, which end before the chat's last message, always take these.
+    fn history_asc<F, Fut>(
+        &self,
+        chat_id: i64,
+        range: MessageRange,
+        limit: Option<usize>,
+        fetch: F,
+    ) -> impl futures::Stream<Item = Result<Message>>
+    where
+        F: Fn(i64, i32, i32) -> Fut,
+        Fut: Future<Output = Result<tdlib_rs::enums::Messages, tdlib_rs::types::Error>>,
+    {
+        async_stream::try_stream! {
+            let mut from_msg_id = match (range.from_id, range.since) {
+                (Some(from_id), _) => from_id,
+                (None, Some(since)) => match self.get_chat_message_by_date(chat_id, since).await? {
+                    Some(msg) => msg.id,
+                    None => crate::message_id_from_server_id(1),
+                },
+                (None, None) => crate::message_id_from_server_id(1),
+            };
+            // The last message yielded; pages overlap by the message they start from.
+            let mut last_msg_id = from_msg_id - 1;
+            let mut count = 0usize;
+
+            let tdlib_rs::enums::Chat::Chat(chat) =
+                tdlib_rs::functions::get_chat(chat_id, self.handle.0)
+                    .await
+                    .map_err(|e| miette!("Failed to get chat {chat_id}: {}", e.message))?;
+            let newest_msg_id = chat.last_message.map_or(0, |msg| msg.id);
+            let mut empty_pages = 0;
+
+            'pages: loop {
+                let limit = if let Some(limit) = limit {
+                    limit.saturating_sub(count).min(99)
+                } else {
+                    99
+                };
+                if limit == 0 {
+                    break;
+                }
+
+                tracing::debug!(count = count, limit, "fetching messages...");
+
+                // A negative offset makes TdLib return the messages that are
+                // newer than `from_msg_id`, in addition to that message itself.
+                let tdlib_rs::enums::Messages::Messages(batch) = tokio::time::timeout(
+                    std::time::Duration::from_secs(60),
+                    fetch(from_msg_id, -(limit as i32), limit as i32 + 1),
+                )
+                .await
+                .map_err(|_| miette!("Request timed out"))?
+                .map_err(|e| miette!("Failed to get message history: {}", e.message))?;
+
+                let mut msgs: Vec<Message> = batch
+                    .messages
+                    .into_iter()
+                    .flatten()
+                    .filter(|msg| msg.id > last_msg_id)
+                    .collect();
+                msgs.sort_unstable_by_key(|msg| msg.id);
+
+                if msgs.is_empty() {
+                    if last_msg_id >= newest_msg_id || empty_pages == EMPTY_PAGE_RETRIES {
+                        break;
+                    }
+                    empty_pages += 1;
+                    tracing::debug!(last_msg_id, newest_msg_id, "got an empty page, retrying...");
+                    tokio::time::sleep(std::time::Duration::from_secs(empty_pages.into())).await;
+                    continue;
+                }
+                empty_pages = 0;
+
+                for msg in msgs {
+                    last_msg_id = msg.id;
+                    from_msg_id = msg.id;
+                    if range.until.is_some_and(|until| i64::from(msg.date) >= until) {
+                        break 'pages;
+                    }
+                    if !range.contains(msg.date.into()) {
+                        continue;
+                    }
+                    count += 1;
+                    yield msg;
+                }
+            }
+        }
+    }
+}
+
+fn search_filter(filter: MessageFilter) -> tdlib_rs::enums::SearchMessagesFilter {
+    use tdlib_rs::enums::SearchMessagesFilter;
+    match filter {
+        MessageFilter::Photo => SearchMessagesFilter::Photo,
+        MessageFilter::Video => SearchMessagesFilter::Video,
+        MessageFilter::Document => SearchMessagesFilter::Document,
+        MessageFilter::Audio => SearchMessagesFilter::Audio,
+        MessageFilter::Voice => SearchMessagesFilter::VoiceNote,
+        MessageFilter::Url => SearchMessagesFilter::Url,
+        MessageFilter::Gif => SearchMessagesFilter::Animation,
     }
 }
 
+/// How often an empty page of an oldest-first message listing is requested
+/// again before the listing ends short of the chat's last message.
+const EMPTY_PAGE_RETRIES: u32 = 3;
+
+/// The characters that member name searches are extended with, by script:
+/// Latin and digits, Cyrillic, Greek, Arabic and Hebrew. Names in scripts
+/// without a small alphabet, such as CJK, are only found through the plain
+/// member listing.
+pub const MEMBER_SEARCH_ALPHABETS: &[&str] = &[
+    "abcdefghijklmnopqrstuvwxyz0123456789",
+    "абвгдеёжзийклмнопрстуфхцчшщъыьэюяіїєґў",
+    "αβγδεζηθικλμνξοπρστυφχψω",
+    "ابتثجحخدذرزسشصضطظعغفقكلمنهويپچژگ",
+    "אבגדהוזחטיכלמנסעפצקרשת",
+];
+
+/// The longest name prefix that members are searched for.
+const MEMBER_SEARCH_MAX_PREFIX: usize = 3;
+
+/// Returns the name prefixes that extend a member search, which stay within
+/// the script of the search's first character to keep their number down.
+fn member_search_prefixes(query: &str) -> Vec<String> {
+    let alphabets = match query.chars().next() {
+        None => MEMBER_SEARCH_ALPHABETS,
+        Some(first) => {
+            let alphabet = MEMBER_SEARCH_ALPHABETS
+                .iter()
+                .position(|alphabet| alphabet.contains(first))
+                .unwrap_or_default();
+            &MEMBER_SEARCH_ALPHABETS[alphabet..=alphabet]
+        }
+    };
+    alphabets
+        .iter()
+        .flat_map(|alphabet| alphabet.chars())
+        .map(|c| format!("{query}{c}"))
+        .collect()
+}
+
+/// Returns how long to wait before retrying a request that failed with a
+/// `FLOOD_WAIT`, e.g. `Too Many Requests: retry after 12`.
+fn flood_wait(err: &tdlib_rs::types::Error) -> Option<std::time::Duration> {
+    if err.code != 429 {
+        return None;
+    }
+    let seconds = err
+        .message
+        .rsplit(|c: char| !c.is_ascii_digit())
+        .find(|s| !s.is_empty())
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(1);
+    Some(std::time::Duration::from_secs(seconds))
+}
+
+/// Tags a serialized object with the chat it belongs to, for TDLib objects
+/// like `chatMember` or `forumTopic` that don't carry that themselves.
+fn with_chat_id(mut object: Value, chat_id: i64) -> Value {
+    if let Some(fields) = object.as_object_mut() {
+        fields.insert("chat_id".into(), chat_id.into());
+    }
+    object
+}
+
+/// Tags a serialized object with the user it belongs to, for TDLib objects
+/// like `chatPhoto` that don't carry that themselves.
+fn with_user_id(mut object: Value, user_id: i64) -> Value {
+    if let Some(fields) = object.as_object_mut() {
+        fields.insert("user_id".into(), user_id.into());
+    }
+    object
+}
+
 pub fn get_or_create_encryption_key() -> Result<String> {
     let entry = keyring::Entry::new("asimov-telegram-module", "tdlib-encryption-key")
         .map_err(|e| miette!("Failed to create keyring entry: {e}"))?;
@@ -519,3 +1898,52 @@ pub fn get_or_create_encryption_key() -> Result<String> {
         )),
     }
 }
+
+/// Deletes the database encryption key from the keyring, if there is one.
+pub fn delete_encryption_key() -> Result<()> {
+    let entry = keyring::Entry::new("asimov-telegram-module", "tdlib-encryption-key")
+        .map_err(|e| miette!("Failed to create keyring entry: {e}"))?;
+
+    match entry.delete_password() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(miette!("Failed to delete encryption key from keyring: {e}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_member_search_prefixes() {
+        let prefixes = member_search_prefixes("");
+        assert!(prefixes.contains(&"a".into()));
+        assert!(prefixes.contains(&"ж".into()));
+        assert!(prefixes.contains(&"ש".into()));
+
+        let prefixes = member_search_prefixes("дм");
+        assert!(prefixes.contains(&"дми".into()));
+        assert!(!prefixes.iter().any(|prefix| prefix == "дмa"));
+        assert_eq!(
+            member_search_prefixes("ab").len(),
+            MEMBER_SEARCH_ALPHABETS[0].len()
+        );
+    }
+
+    #[test]
+    fn test_flood_wait() {
+        let error = |code, message: &str| tdlib_rs::types::Error {
+            code,
+            message: message.into(),
+        };
+        assert_eq!(
+            flood_wait(&error(429, "Too Many Requests: retry after 12")),
+            Some(std::time::Duration::from_secs(12))
+        );
+        assert_eq!(
+            flood_wait(&error(429, "Too Many Requests")),
+            Some(std::time::Duration::from_secs(1))
+        );
+        assert_eq!(flood_wait(&error(400, "Member list is inaccessible")), None);
+    }
+}